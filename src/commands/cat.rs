@@ -1,43 +1,215 @@
-use std::fs;
-use std::io::{self, BufRead, Write};
-
-pub fn cat(args: &[String]) -> String {
-    // Function to read stdin interactively
-    fn open_buffer() {
-        let stdin = io::stdin();
-        let stdout = io::stdout();
-        let mut handle_out = stdout.lock();
-
-        for line in stdin.lock().lines() {
-            match line {
-                Ok(l) => {
-                    // Print immediately
-                    writeln!(handle_out, "{}", l).unwrap();
-                }
-                Err(e) => {
-                    writeln!(handle_out, "cat: stdin: {}", e).unwrap();
-                    break;
-                }
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use crate::commands::options::{Opt, Spec};
+
+static SPEC: Spec = Spec::new(
+    "cat",
+    "[OPTION]... [FILE]...",
+    "Concatenate FILE(s) to standard output.\n\nWith no FILE, or when FILE is -, read standard input.",
+    &[
+        Opt::flag('A', "show-all", "equivalent to -vET"),
+        Opt::flag('b', "number-nonblank", "number nonempty output lines, overrides -n"),
+        Opt::flag('e', "", "equivalent to -vE"),
+        Opt::flag('E', "show-ends", "display $ at end of each line"),
+        Opt::flag('n', "number", "number all output lines"),
+        Opt::flag('s', "squeeze-blank", "suppress repeated empty output lines"),
+        Opt::flag('t', "", "equivalent to -vT"),
+        Opt::flag('T', "show-tabs", "display TAB characters as ^I"),
+        Opt::flag('u', "", "(ignored)"),
+        Opt::flag('v', "show-nonprinting", "use ^ and M- notation, except for LFD and TAB"),
+    ],
+);
+
+const BUFFER: usize = 64 * 1024;
+
+/// How lines are decorated; all off means bytes pass straight through
+struct Format {
+    number: bool,
+    number_nonblank: bool,
+    squeeze_blank: bool,
+    show_ends: bool,
+    show_tabs: bool,
+    show_nonprinting: bool,
+}
+
+impl Format {
+    fn is_plain(&self) -> bool {
+        !(self.number || self.number_nonblank || self.squeeze_blank || self.show_ends || self.show_tabs || self.show_nonprinting)
+    }
+}
+
+/// Line state carried from one file to the next, as numbering and blank
+/// squeezing run across the whole output
+struct State {
+    line: u64,
+    at_line_start: bool,
+    previous_blank: bool,
+}
+
+pub fn cat(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let all = matches.has("show-all");
+    let format = Format {
+        number: matches.has("number"),
+        number_nonblank: matches.has("number-nonblank"),
+        squeeze_blank: matches.has("squeeze-blank"),
+        show_ends: all || matches.has("show-ends") || matches.has("e"),
+        show_tabs: all || matches.has("show-tabs") || matches.has("t"),
+        show_nonprinting: all || matches.has("show-nonprinting") || matches.has("e") || matches.has("t"),
+    };
+    let mut state = State { line: 0, at_line_start: true, previous_blank: false };
+
+    let stdin_only = ["-".to_string()];
+    let files = if matches.operands.is_empty() { &stdin_only[..] } else { &matches.operands[..] };
+
+    let mut status = 0;
+
+    for filename in files {
+        let result = if filename == "-" {
+            // Flush as we go so typed lines echo back straight away
+            let stdin = io::stdin();
+            let mut input = stdin.lock();
+            copy(&mut input, out, &format, &mut state, true)
+        } else {
+            match open(filename) {
+                Ok(mut file) => copy(&mut file, out, &format, &mut state, false),
+                Err(e) => Err(e),
+            }
+        };
+
+        if let Err(e) = result {
+            // Errors writing out end cat altogether; errors reading in
+            // only skip that file
+            if let Error::Write(e) = e {
+                return Err(e);
             }
+            writeln!(err, "cat: {}: {}", filename, e)?;
+            status = 1;
         }
     }
 
-    // If no arguments → read from stdin interactively
-    if args.is_empty() {
-        open_buffer();
-        return String::new(); // Return empty string
+    Ok(status)
+}
+
+enum Error {
+    Read(io::Error),
+    Write(io::Error),
+    IsDirectory,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Read(e) | Error::Write(e) => write!(f, "{}", e),
+            Error::IsDirectory => write!(f, "Is a directory"),
+        }
     }
+}
 
-    for (_, filename) in args.iter().enumerate() {
-        if filename == "-" {
-            open_buffer();
-        } else {
-            match fs::read_to_string(filename) {
-                Ok(content) => print!("{}\n", content),
-                Err(e) => eprintln!("cat: {}: {}", filename, e),
+fn open(filename: &str) -> Result<BufReader<File>, Error> {
+    // A directory opens fine but can't be read; say so up front
+    if fs::metadata(filename).is_ok_and(|meta| meta.is_dir()) {
+        return Err(Error::IsDirectory);
+    }
+    File::open(filename).map(|file| BufReader::with_capacity(BUFFER, file)).map_err(Error::Read)
+}
+
+fn copy(input: &mut dyn BufRead, out: &mut dyn Write, format: &Format, state: &mut State, flush: bool) -> Result<(), Error> {
+    if format.is_plain() {
+        // Byte for byte; works for FIFOs and /proc files, which report no size
+        let mut buffer = vec![0; BUFFER];
+        loop {
+            let n = match input.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Read(e)),
+            };
+            out.write_all(&buffer[..n]).map_err(Error::Write)?;
+            if flush {
+                out.flush().map_err(Error::Write)?;
             }
         }
     }
 
-    String::new()
+    // Decorate buffer by buffer rather than line by line, so a stream with
+    // no newlines (`cat -v /dev/zero`) doesn't pile up in memory
+    let mut shown = Vec::with_capacity(BUFFER * 4);
+    loop {
+        let chunk = match input.fill_buf() {
+            Ok([]) => return Ok(()),
+            Ok(chunk) => chunk,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::Read(e)),
+        };
+        shown.clear();
+        for &b in chunk {
+            decorate(b, format, state, &mut shown);
+        }
+        let used = chunk.len();
+        input.consume(used);
+        out.write_all(&shown).map_err(Error::Write)?;
+        if flush {
+            out.flush().map_err(Error::Write)?;
+        }
+    }
+}
+
+/// Append byte `b` to `shown` with whatever decorations apply where it
+/// falls: a line number before a line's first byte, `$` before a newline,
+/// nothing at all for a squeezed blank line
+fn decorate(b: u8, format: &Format, state: &mut State, shown: &mut Vec<u8>) {
+    if state.at_line_start {
+        let blank = b == b'\n';
+        if format.squeeze_blank && blank && state.previous_blank {
+            return;
+        }
+        state.previous_blank = blank;
+
+        if (format.number_nonblank && !blank) || (format.number && !format.number_nonblank) {
+            state.line += 1;
+            shown.extend_from_slice(format!("{:>6}\t", state.line).as_bytes());
+        }
+    }
+    state.at_line_start = b == b'\n';
+
+    if b == b'\n' {
+        if format.show_ends {
+            shown.push(b'$');
+        }
+        shown.push(b);
+    } else {
+        show_byte(b, format, shown);
+    }
+}
+
+/// `^I` for tabs under -T; `^X`, `^?` and `M-` notation under -v
+fn show_byte(b: u8, format: &Format, shown: &mut Vec<u8>) {
+    if b == b'\t' {
+        if format.show_tabs {
+            shown.extend_from_slice(b"^I");
+        } else {
+            shown.push(b);
+        }
+        return;
+    }
+    if !format.show_nonprinting {
+        shown.push(b);
+        return;
+    }
+
+    let low = if b >= 128 {
+        shown.extend_from_slice(b"M-");
+        b - 128
+    } else {
+        b
+    };
+    match low {
+        0..=31 => shown.extend_from_slice(&[b'^', low + 64]),
+        127 => shown.extend_from_slice(b"^?"),
+        _ => shown.push(low),
+    }
 }
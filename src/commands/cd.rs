@@ -1,59 +1,104 @@
 use std::env;
+use std::io::{self, Write};
 use std::path::Path;
+use crate::commands::options::{Opt, Spec};
+use crate::commands::pwd::{logical, normalize};
 
-pub fn cd(args: &[String]) -> String {
-    // Save current directory
-   let current_dir = env::current_dir()
-        .map(|d| d.to_string_lossy().into_owned())
-        .unwrap_or_else(|_| String::new());
+static SPEC: Spec = Spec::new(
+    "cd",
+    "[-L|-P] [DIR]",
+    "Change the shell working directory to DIR (HOME by default; - means OLDPWD).\n\n\
+     By default symbolic links are followed logically: `..` removes the\n\
+     previous component of PWD rather than going to the physical parent.",
+    &[
+        Opt::flag('L', "", "follow symbolic links logically, resolving .. against PWD (default)"),
+        Opt::flag('P', "", "use the physical directory structure, resolving symbolic links"),
+    ],
+);
+
+pub fn cd(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let physical = matches.last_of(&["L", "P"]) == Some("P");
+    let args = &matches.operands;
+    if args.len() > 1 {
+        writeln!(err, "cd: too many arguments")?;
+        return Ok(1);
+    }
+
+    // Save current directory, logically if PWD can be trusted
+    let current_dir = logical()
+        .or_else(|| env::current_dir().ok().map(|d| d.to_string_lossy().into_owned()))
+        .unwrap_or_default();
     // Determine target directory
-    let target = if args.is_empty() {
-        env::var("HOME").or_else(|_| env::var("USERPROFILE")).unwrap_or_else(|_| {
-            return "cd: No home directory found (HOME or USERPROFILE not set)".to_string();
-        })
-    } else {
-        let arg = args[0].trim();
-        if arg == "-" {
-            env::var("OLDPWD").unwrap_or_else(|_| {
-                return "cd: OLDPWD not set".to_string();
-            })
-        } else if arg == "~" {
-            env::var("HOME").or_else(|_| env::var("USERPROFILE")).unwrap_or_else(|_| {
-                return "cd: No home directory found (HOME or USERPROFILE not set)".to_string();
-            })
-        } else {
-            args[0].clone()
+    let from_oldpwd = !args.is_empty() && args[0].trim() == "-";
+    let target = if args.is_empty() || args[0].trim() == "~" {
+        match env::var("HOME").or_else(|_| env::var("USERPROFILE")) {
+            Ok(home) => home,
+            Err(_) => {
+                writeln!(err, "cd: No home directory found (HOME or USERPROFILE not set)")?;
+                return Ok(1);
+            }
         }
+    } else if from_oldpwd {
+        match env::var("OLDPWD") {
+            Ok(old) => old,
+            Err(_) => {
+                writeln!(err, "cd: OLDPWD not set")?;
+                return Ok(1);
+            }
+        }
+    } else {
+        args[0].clone()
+    };
+
+    // Logically, the new directory is worked out from PWD by text, so that
+    // `..` out of a symlinked directory goes back where it came from
+    let logical_target = if physical || current_dir.is_empty() {
+        None
+    } else {
+        Some(normalize(&Path::new(&current_dir).join(&target)))
     };
 
     // Attempt to change directory
-    let result = change_directory(&target);
+    let result = match &logical_target {
+        Some(path) => change_directory(path, &target),
+        None => change_directory(Path::new(&target), &target),
+    };
+    if let Err(message) = result {
+        writeln!(err, "{}", message)?;
+        return Ok(1);
+    }
 
-    // If successful, update OLDPWD
-    if result.is_empty() && !current_dir.is_empty() {
-    env::set_var("OLDPWD", current_dir);
+    // If successful, update OLDPWD and PWD
+    if !current_dir.is_empty() {
+        env::set_var("OLDPWD", current_dir);
+    }
+    let new_dir = match logical_target {
+        Some(path) => path.to_string_lossy().into_owned(),
+        None => env::current_dir().map(|d| d.to_string_lossy().into_owned()).unwrap_or_default(),
+    };
+    env::set_var("PWD", &new_dir);
 
-    if let Ok(new_dir) = env::current_dir() {
-        env::set_var("PWD", new_dir.to_string_lossy().to_string());
+    // `cd -` says where it went
+    if from_oldpwd {
+        writeln!(out, "{}", new_dir)?;
     }
-}
 
-    result
+    Ok(0)
 }
 
-pub fn change_directory(path: &str) -> String {
-    let target = Path::new(path);
-
+/// Change to `target`, naming `operand` (what the user typed) in errors
+pub fn change_directory(target: &Path, operand: &str) -> Result<(), String> {
     if !target.exists() {
-        return format!("cd: {}: No such file or directory\n", path);
+        return Err(format!("cd: {}: No such file or directory", operand));
     }
 
     if !target.is_dir() {
-        return format!("cd: {}: Not a directory\n", path);
+        return Err(format!("cd: {}: Not a directory", operand));
     }
 
-    match env::set_current_dir(target) {
-        Ok(_) => String::new(),
-        Err(e) => format!("cd: {}: {}\n", path, e),
-    }
+    env::set_current_dir(target).map_err(|e| format!("cd: {}: {}", operand, e))
 }
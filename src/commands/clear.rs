@@ -1,4 +1,14 @@
-pub fn clear() -> String {
+use std::io::{self, Write};
+use crate::commands::options::Spec;
+
+static SPEC: Spec = Spec::new("clear", "", "Clear the terminal screen.", &[]);
+
+pub fn clear(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    if let Err(e) = SPEC.parse(args) {
+        return e.report(out, err);
+    }
+
     // ANSI escape code to clear screen and move cursor to top-left
-    "\x1Bc".to_string()
+    write!(out, "\x1Bc")?;
+    Ok(0)
 }
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{lchown, symlink, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::commands::overwrite::Policy;
+use crate::commands::umask::current_umask;
+use crate::commands::xattr;
+
+mod data;
+mod progress;
+
+pub use self::data::{Reflink, Sparse};
+use self::progress::Progress;
+
+/// What `-p` and a bare `--preserve` keep
+pub const BASIC_ATTRIBUTES: &str = "mode,ownership,timestamps";
+
+/// File attributes carried over to the copy
+#[derive(Clone, Copy, Default)]
+pub struct Preserve {
+    pub mode: bool,
+    pub ownership: bool,
+    pub timestamps: bool,
+    pub xattr: bool,
+    /// Recreate hard links between sources instead of copying twice
+    pub links: bool,
+}
+
+impl Preserve {
+    pub fn all() -> Preserve {
+        Preserve { mode: true, ownership: true, timestamps: true, xattr: true, links: true }
+    }
+
+    /// Add the attributes named in a `--preserve=LIST` argument
+    pub fn add_list(&mut self, list: &str) -> Result<(), String> {
+        for attr in list.split(',') {
+            match attr {
+                "mode" => self.mode = true,
+                "ownership" => self.ownership = true,
+                "timestamps" => self.timestamps = true,
+                "xattr" => self.xattr = true,
+                "links" => self.links = true,
+                "all" => *self = Preserve::all(),
+                _ => return Err(attr.to_string()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which symlinks to follow
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dereference {
+    /// `-P`: copy every symlink as a symlink
+    Never,
+    /// `-H`: follow symlinks named on the command line only
+    CommandLine,
+    /// `-L`: follow every symlink
+    Always,
+}
+
+/// What ends up at the destination for a non-directory
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    Copy,
+    /// `-l`: a hard link to the source
+    Hard,
+    /// `-s`: a symbolic link to the source
+    Symbolic,
+}
+
+pub struct CopyOptions {
+    /// Descend into directories
+    pub recursive: bool,
+    pub dereference: Dereference,
+    pub link: LinkMode,
+    pub preserve: Preserve,
+    /// What to do about destinations that already exist
+    pub overwrite: Policy,
+    pub reflink: Reflink,
+    pub sparse: Sparse,
+    /// `-v`: name each file as it is copied
+    pub verbose: bool,
+    /// `--progress`: draw a progress bar for each file
+    pub progress: bool,
+}
+
+/// Copies files and trees, reporting failures as `<prog>: ...` on `err`
+/// and carrying on with the rest, the way cp does
+pub struct Copier<'a> {
+    opts: &'a CopyOptions,
+    prog: &'static str,
+    out: &'a mut dyn Write,
+    err: &'a mut dyn Write,
+    /// Destination of every multiply-linked source seen, by (dev, inode)
+    copied: HashMap<(u64, u64), PathBuf>,
+    /// Directories being copied, to catch loops through followed symlinks
+    ancestors: Vec<(u64, u64)>,
+    /// Set once anything failed to copy
+    pub failed: bool,
+}
+
+impl<'a> Copier<'a> {
+    pub fn new(opts: &'a CopyOptions, prog: &'static str, out: &'a mut dyn Write, err: &'a mut dyn Write) -> Copier<'a> {
+        Copier { opts, prog, out, err, copied: HashMap::new(), ancestors: Vec::new(), failed: false }
+    }
+
+    /// Where diagnostics go, for callers reporting their own errors
+    pub fn err(&mut self) -> &mut dyn Write {
+        &mut *self.err
+    }
+
+    fn fail(&mut self, message: String) -> io::Result<()> {
+        self.failed = true;
+        writeln!(self.err, "{}: {}", self.prog, message)
+    }
+
+    /// Copy the command-line operand `src` to exactly `dst`
+    pub fn copy(&mut self, src: &Path, dst: &Path) -> io::Result<()> {
+        let meta = match self.source_metadata(src, true) {
+            Ok(meta) => meta,
+            Err(e) => return self.fail(format!("cannot stat '{}': {}", src.display(), e)),
+        };
+
+        if meta.is_dir() {
+            if !self.opts.recursive {
+                return self.fail(format!("-r not specified; omitting directory '{}'", src.display()));
+            }
+            if is_inside(src, dst) {
+                return self.fail(format!(
+                    "cannot copy a directory, '{}', into itself, '{}'",
+                    src.display(),
+                    dst.display()
+                ));
+            }
+        }
+
+        if let Ok(dst_meta) = fs::metadata(dst) {
+            if dst_meta.dev() == meta.dev() && dst_meta.ino() == meta.ino() {
+                return self.fail(format!("'{}' and '{}' are the same file", src.display(), dst.display()));
+            }
+        }
+
+        self.copy_entry(src, dst, &meta)
+    }
+
+    fn source_metadata(&self, src: &Path, command_line: bool) -> io::Result<fs::Metadata> {
+        let follow = match self.opts.dereference {
+            Dereference::Never => false,
+            Dereference::CommandLine => command_line,
+            Dereference::Always => true,
+        };
+        if follow {
+            fs::metadata(src)
+        } else {
+            fs::symlink_metadata(src)
+        }
+    }
+
+    fn copy_entry(&mut self, src: &Path, dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+        if meta.is_dir() {
+            return self.copy_dir(src, dst, meta);
+        }
+
+        let mut backup = None;
+        if let Ok(dst_meta) = fs::symlink_metadata(dst) {
+            if dst_meta.is_dir() {
+                return self.fail(format!(
+                    "cannot overwrite directory '{}' with non-directory '{}'",
+                    dst.display(),
+                    src.display()
+                ));
+            }
+            if !self.opts.overwrite.allows(self.prog, meta, dst, &dst_meta, &mut *self.err)? {
+                return Ok(());
+            }
+            backup = match self.opts.overwrite.make_backup(dst) {
+                Ok(backup) => backup,
+                Err(e) => return self.fail(format!("cannot backup '{}': {}", dst.display(), e)),
+            };
+            // A link can't be created over an existing file
+            if self.opts.link != LinkMode::Copy && fs::symlink_metadata(dst).is_ok() {
+                if let Err(e) = fs::remove_file(dst) {
+                    return self.fail(format!("cannot remove '{}': {}", dst.display(), e));
+                }
+            }
+        }
+
+        // Later names for an inode already copied become hard links to the copy
+        let key = (meta.dev(), meta.ino());
+        let track = meta.nlink() > 1 && (self.opts.recursive || self.opts.preserve.links);
+        let linked_to = if track { self.copied.get(&key).cloned() } else { None };
+
+        let result = match self.opts.link {
+            LinkMode::Hard => fs::hard_link(src, dst)
+                .map_err(|e| format!("cannot create hard link '{}' to '{}': {}", dst.display(), src.display(), e)),
+            // A relative target would resolve against the link's directory
+            LinkMode::Symbolic if src.is_relative() && dst.parent().is_some_and(|p| !p.as_os_str().is_empty()) => {
+                Err(format!("{}: can make relative symbolic links only in current directory", dst.display()))
+            }
+            LinkMode::Symbolic => symlink(src, dst)
+                .map_err(|e| format!("cannot create symbolic link '{}' to '{}': {}", dst.display(), src.display(), e)),
+            LinkMode::Copy => match linked_to {
+                Some(ref first) => fs::hard_link(first, dst)
+                    .map_err(|e| format!("cannot create hard link '{}' to '{}': {}", dst.display(), first.display(), e)),
+                None => self
+                    .copy_data(src, dst, meta)
+                    .map_err(|e| format!("cannot copy '{}' to '{}': {}", src.display(), dst.display(), e)),
+            },
+        };
+        if let Err(message) = result {
+            return self.fail(message);
+        }
+        self.report(src, dst, backup.as_deref())?;
+
+        if self.opts.link != LinkMode::Copy || linked_to.is_some() {
+            return Ok(());
+        }
+        if track {
+            self.copied.insert(key, dst.to_path_buf());
+        }
+        self.apply_attributes(src, dst, meta)
+    }
+
+    /// Write a copy of the non-directory `src`
+    fn copy_data(&mut self, src: &Path, dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+        let ft = meta.file_type();
+        if ft.is_symlink() {
+            return copy_symlink(src, dst);
+        }
+        if !ft.is_file() {
+            return make_special(dst, meta);
+        }
+        match self.copy_file(src, dst, meta) {
+            // -f: a destination we can't write to is removed and replaced
+            Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied && self.opts.overwrite.force => {
+                fs::remove_file(dst)?;
+                self.copy_file(src, dst, meta)
+            }
+            result => result,
+        }
+    }
+
+    fn copy_file(&mut self, src: &Path, dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+        let (reflink, sparse) = (self.opts.reflink, self.opts.sparse);
+        if !self.opts.progress {
+            return data::copy_file(src, dst, reflink, sparse, &mut |_| {});
+        }
+
+        let err = &mut *self.err;
+        let mut bar = Progress::new(src, meta.len());
+        // The bar is best effort: failing to draw it doesn't fail the copy
+        let result = data::copy_file(src, dst, reflink, sparse, &mut |bytes| {
+            let _ = bar.advance(bytes, err);
+        });
+        let _ = bar.finish(err);
+        result
+    }
+
+    /// `-v`: say what went where
+    fn report(&mut self, src: &Path, dst: &Path, backup: Option<&Path>) -> io::Result<()> {
+        if !self.opts.verbose {
+            return Ok(());
+        }
+        match backup {
+            Some(backup) => writeln!(self.out, "'{}' -> '{}' (backup: '{}')", src.display(), dst.display(), backup.display()),
+            None => writeln!(self.out, "'{}' -> '{}'", src.display(), dst.display()),
+        }
+    }
+
+    fn copy_dir(&mut self, src: &Path, dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+        let key = (meta.dev(), meta.ino());
+        if self.ancestors.contains(&key) {
+            return self.fail(format!("cannot copy cyclic symbolic link '{}'", src.display()));
+        }
+        self.ancestors.push(key);
+        let result = self.copy_dir_contents(src, dst, meta);
+        self.ancestors.pop();
+        result
+    }
+
+    fn copy_dir_contents(&mut self, src: &Path, dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+        let created = match fs::symlink_metadata(dst) {
+            Ok(existing) if existing.is_dir() => false,
+            Ok(_) => {
+                return self.fail(format!(
+                    "cannot overwrite non-directory '{}' with directory '{}'",
+                    dst.display(),
+                    src.display()
+                ));
+            }
+            Err(_) => {
+                if let Err(e) = fs::create_dir(dst) {
+                    return self.fail(format!("cannot create directory '{}': {}", dst.display(), e));
+                }
+                // Keep it writable for now; the real mode is applied afterwards
+                let _ = fs::set_permissions(dst, fs::Permissions::from_mode(meta.mode() & 0o7777 | 0o700));
+                self.report(src, dst, None)?;
+                true
+            }
+        };
+
+        let entries = match fs::read_dir(src) {
+            Ok(entries) => entries,
+            Err(e) => return self.fail(format!("cannot access '{}': {}", src.display(), e)),
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.fail(format!("cannot access '{}': {}", src.display(), e))?;
+                    continue;
+                }
+            };
+            let child_src = entry.path();
+            let child_dst = dst.join(entry.file_name());
+            match self.source_metadata(&child_src, false) {
+                Ok(child_meta) => self.copy_entry(&child_src, &child_dst, &child_meta)?,
+                Err(e) => self.fail(format!("cannot stat '{}': {}", child_src.display(), e))?,
+            }
+        }
+
+        // A new directory without --preserve=mode gets the source's mode
+        // less the umask, like mkdir would
+        if created && !self.opts.preserve.mode {
+            let _ = fs::set_permissions(dst, fs::Permissions::from_mode(meta.mode() & 0o777 & !current_umask()));
+        }
+        // Attributes last, since filling the directory just changed its times
+        self.apply_attributes(src, dst, meta)
+    }
+
+    /// Apply whatever `--preserve` asks for. Failing to keep ownership is
+    /// only an error for root, as with coreutils.
+    fn apply_attributes(&mut self, src: &Path, dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+        let preserve = self.opts.preserve;
+        let is_link = meta.file_type().is_symlink();
+
+        if preserve.xattr {
+            if let Ok(names) = xattr::list(src) {
+                for name in names {
+                    let copied = xattr::get(src, &name).and_then(|value| xattr::set(dst, &name, &value));
+                    if let Err(e) = copied {
+                        if e.raw_os_error() != Some(libc::ENOTSUP) && e.raw_os_error() != Some(libc::EPERM) {
+                            self.fail(format!("setting attribute '{}' for '{}': {}", name.to_string_lossy(), dst.display(), e))?;
+                        }
+                    }
+                }
+            }
+        }
+
+        if preserve.ownership {
+            if let Err(e) = lchown(dst, Some(meta.uid()), Some(meta.gid())) {
+                // SAFETY: geteuid has no preconditions
+                if unsafe { libc::geteuid() } == 0 {
+                    self.fail(format!("failed to preserve ownership for '{}': {}", dst.display(), e))?;
+                }
+            }
+        }
+
+        // chown clears setuid/setgid, so the mode goes on afterwards
+        if preserve.mode && !is_link {
+            if let Err(e) = fs::set_permissions(dst, fs::Permissions::from_mode(meta.mode() & 0o7777)) {
+                self.fail(format!("preserving permissions for '{}': {}", dst.display(), e))?;
+            }
+        }
+
+        if preserve.timestamps {
+            if let Err(e) = set_times(dst, meta) {
+                self.fail(format!("preserving times for '{}': {}", dst.display(), e))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether copying directory `src` to `dst` would put it inside itself
+pub fn is_inside(src: &Path, dst: &Path) -> bool {
+    let src = match src.canonicalize() {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    // dst itself usually doesn't exist yet, so resolve its parent
+    let dst = match dst.canonicalize() {
+        Ok(p) => p,
+        Err(_) => match (dst.parent(), dst.file_name()) {
+            (Some(parent), Some(name)) => {
+                let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+                match parent.canonicalize() {
+                    Ok(p) => p.join(name),
+                    Err(_) => return false,
+                }
+            }
+            _ => return false,
+        },
+    };
+    dst.starts_with(&src)
+}
+
+/// Name to use for `src` inside a destination directory: its last
+/// component, or that of the path it resolves to for things like `..`
+pub fn target_name(src: &Path) -> Option<PathBuf> {
+    if let Some(name) = src.file_name() {
+        return Some(PathBuf::from(name));
+    }
+    src.canonicalize().ok()?.file_name().map(PathBuf::from)
+}
+
+fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    let target = fs::read_link(src)?;
+    if fs::symlink_metadata(dst).is_ok() {
+        fs::remove_file(dst)?;
+    }
+    symlink(target, dst)
+}
+
+/// Recreate a FIFO, socket or device node
+fn make_special(dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    let c_dst = CString::new(dst.as_os_str().as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: c_dst is a valid NUL-terminated path
+    let rc = unsafe { libc::mknod(c_dst.as_ptr(), (meta.mode() & (libc::S_IFMT | 0o777)) as libc::mode_t, meta.rdev() as libc::dev_t) };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Copy access and modification times, to the nanosecond, without
+/// following symlinks
+fn set_times(dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    let c_dst = CString::new(dst.as_os_str().as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let times = [
+        libc::timespec { tv_sec: meta.atime() as libc::time_t, tv_nsec: meta.atime_nsec() as _ },
+        libc::timespec { tv_sec: meta.mtime() as libc::time_t, tv_nsec: meta.mtime_nsec() as _ },
+    ];
+    // SAFETY: c_dst is a valid path and times holds two timespecs
+    let rc = unsafe { libc::utimensat(libc::AT_FDCWD, c_dst.as_ptr(), times.as_ptr(), libc::AT_SYMLINK_NOFOLLOW) };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use std::fs::{File, Metadata, OpenOptions};
+use std::io;
+use std::os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Bytes handed to each copy_file_range call, small enough that progress
+/// keeps moving on huge files
+const CHUNK: usize = 8 << 20;
+/// Buffer for the read/write fallback
+const BUFFER: usize = 128 << 10;
+
+/// `--reflink=WHEN`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Reflink {
+    /// Clone when the filesystem can, copy otherwise
+    Auto,
+    /// Clone or fail
+    Always,
+    Never,
+}
+
+impl Reflink {
+    pub fn parse(arg: &str) -> Option<Reflink> {
+        match arg {
+            "auto" => Some(Reflink::Auto),
+            "always" => Some(Reflink::Always),
+            "never" => Some(Reflink::Never),
+            _ => None,
+        }
+    }
+}
+
+/// `--sparse=WHEN`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Sparse {
+    /// Keep the holes of a source that has them
+    Auto,
+    /// Also turn runs of zeros into holes
+    Always,
+    /// Write every byte
+    Never,
+}
+
+impl Sparse {
+    pub fn parse(arg: &str) -> Option<Sparse> {
+        match arg {
+            "auto" => Some(Sparse::Auto),
+            "always" => Some(Sparse::Always),
+            "never" => Some(Sparse::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Copy the contents of regular file `src` to `dst`, creating or
+/// truncating it. `progress` hears about every run of bytes dealt with,
+/// holes included.
+pub fn copy_file(src: &Path, dst: &Path, reflink: Reflink, sparse: Sparse, progress: &mut dyn FnMut(u64)) -> io::Result<()> {
+    let input = File::open(src)?;
+    let meta = input.metadata()?;
+    let output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(meta.mode() & 0o777)
+        .open(dst)?;
+    // A new file gets the permission bits less the umask, and never the
+    // setuid, setgid or sticky bits; an existing one keeps its mode. Only
+    // --preserve=mode copies the mode exactly, once the data is in.
+
+    if reflink != Reflink::Never {
+        match clone(&input, &output) {
+            Ok(()) => {
+                progress(meta.len());
+                return Ok(());
+            }
+            Err(e) if reflink == Reflink::Always => return Err(e),
+            Err(_) => {}
+        }
+    }
+
+    let mut data = Data { input: &input, output: &output, offload: true, progress };
+    let end = match sparse {
+        Sparse::Auto if is_sparse(&meta) => data.extents(meta.len())?,
+        Sparse::Always => data.range(0, u64::MAX, true)?,
+        _ => data.range(0, u64::MAX, false)?,
+    };
+    // Holes at the end only exist once the size is set
+    if sparse != Sparse::Never {
+        output.set_len(end)?;
+    }
+    Ok(())
+}
+
+/// Fewer blocks than the size needs means the file has holes
+fn is_sparse(meta: &Metadata) -> bool {
+    meta.blocks() * 512 < meta.len()
+}
+
+/// Share the source's extents with FICLONE, as on btrfs and xfs
+fn clone(input: &File, output: &File) -> io::Result<()> {
+    // SAFETY: both descriptors are open for as long as the files live
+    let rc = unsafe { libc::ioctl(output.as_raw_fd(), libc::FICLONE, input.as_raw_fd()) };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+struct Data<'a> {
+    input: &'a File,
+    output: &'a File,
+    /// Whether copy_file_range is still worth trying
+    offload: bool,
+    progress: &'a mut dyn FnMut(u64),
+}
+
+impl Data<'_> {
+    /// Copy the data extents found with SEEK_DATA/SEEK_HOLE, skipping the
+    /// holes between them. Returns the size the copy should have.
+    fn extents(&mut self, len: u64) -> io::Result<u64> {
+        let fd = self.input.as_raw_fd();
+        let mut offset = 0;
+        while offset < len {
+            let data = match seek(fd, offset, libc::SEEK_DATA) {
+                Ok(data) => data,
+                // Nothing but a hole left
+                Err(ref e) if e.raw_os_error() == Some(libc::ENXIO) => break,
+                // No hole support after all: look for zeros instead
+                Err(_) => return self.range(offset, u64::MAX, true),
+            };
+            let hole = seek(fd, data, libc::SEEK_HOLE)?;
+            (self.progress)(data - offset);
+            self.range(data, hole, false)?;
+            offset = hole;
+        }
+        (self.progress)(len.saturating_sub(offset));
+        Ok(len)
+    }
+
+    /// Copy `start..end`, or up to end of file. With `holes`, blocks of
+    /// zeros are skipped rather than written. Returns where copying stopped.
+    fn range(&mut self, start: u64, end: u64, holes: bool) -> io::Result<u64> {
+        let mut offset = start;
+        // Zero detection needs to see the bytes, so it can't be offloaded
+        while self.offload && !holes && offset < end {
+            let want = (end - offset).min(CHUNK as u64) as usize;
+            match self.offloaded(offset, want) {
+                // Some files (in /proc, say) claim to be empty to
+                // copy_file_range; read those the slow way
+                Ok(0) if offset == 0 => self.offload = false,
+                Ok(0) => return Ok(offset),
+                Ok(copied) => {
+                    offset += copied as u64;
+                    (self.progress)(copied as u64);
+                }
+                Err(e) => match e.raw_os_error() {
+                    Some(libc::ENOSYS) | Some(libc::EXDEV) | Some(libc::EINVAL) | Some(libc::EOPNOTSUPP)
+                    | Some(libc::EBADF) | Some(libc::EPERM) | Some(libc::ETXTBSY) => self.offload = false,
+                    _ => return Err(e),
+                },
+            }
+        }
+
+        let mut buffer = vec![0; BUFFER];
+        while offset < end {
+            let want = (end - offset).min(BUFFER as u64) as usize;
+            let read = match self.input.read_at(&mut buffer[..want], offset) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let chunk = &buffer[..read];
+            if !(holes && chunk.iter().all(|&b| b == 0)) {
+                self.output.write_all_at(chunk, offset)?;
+            }
+            offset += read as u64;
+            (self.progress)(read as u64);
+        }
+        Ok(offset)
+    }
+
+    /// One copy_file_range call at matching offsets in both files
+    fn offloaded(&mut self, offset: u64, len: usize) -> io::Result<usize> {
+        let mut off_in = offset as libc::loff_t;
+        let mut off_out = offset as libc::loff_t;
+        // SAFETY: both descriptors are open and the offsets are valid for
+        // the duration of the call
+        let copied = unsafe {
+            libc::copy_file_range(self.input.as_raw_fd(), &mut off_in, self.output.as_raw_fd(), &mut off_out, len, 0)
+        };
+        if copied < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(copied as usize)
+    }
+}
+
+fn seek(fd: libc::c_int, offset: u64, whence: libc::c_int) -> io::Result<u64> {
+    // SAFETY: lseek only moves the file offset of an open descriptor
+    let pos = unsafe { libc::lseek(fd, offset as libc::off_t, whence) };
+    if pos < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(pos as u64)
+}
@@ -0,0 +1,81 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::commands::size;
+
+/// How often the bar is redrawn
+const REFRESH: Duration = Duration::from_millis(200);
+const BAR_WIDTH: usize = 20;
+
+/// `--progress`: a one-line bar on stderr with bytes copied, throughput
+/// and time left for the file being copied
+pub struct Progress {
+    name: String,
+    total: u64,
+    done: u64,
+    start: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(name: &Path, total: u64) -> Progress {
+        Progress {
+            name: name.display().to_string(),
+            total,
+            done: 0,
+            start: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    pub fn advance(&mut self, bytes: u64, err: &mut dyn Write) -> io::Result<()> {
+        self.done += bytes;
+        match self.drawn {
+            Some(at) if at.elapsed() < REFRESH => Ok(()),
+            _ => self.draw(err),
+        }
+    }
+
+    /// Draw the final state and move off the line
+    pub fn finish(&mut self, err: &mut dyn Write) -> io::Result<()> {
+        self.draw(err)?;
+        writeln!(err)
+    }
+
+    fn draw(&mut self, err: &mut dyn Write) -> io::Result<()> {
+        self.drawn = Some(Instant::now());
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { self.done as f64 / elapsed } else { 0.0 };
+        let fraction = if self.total == 0 { 1.0 } else { (self.done as f64 / self.total as f64).min(1.0) };
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let eta = if rate > 0.0 {
+            clock((self.total.saturating_sub(self.done) as f64 / rate) as u64)
+        } else {
+            "--:--".to_string()
+        };
+
+        write!(
+            err,
+            "\r{} [{}{}] {:>3}% {}/{} {}/s ETA {}\x1B[K",
+            self.name,
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            (fraction * 100.0) as u32,
+            size::human(self.done, 1024),
+            size::human(self.total, 1024),
+            size::human(rate as u64, 1024),
+            eta
+        )?;
+        err.flush()
+    }
+}
+
+/// `MM:SS`, or `H:MM:SS` from an hour up
+fn clock(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
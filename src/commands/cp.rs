@@ -1,41 +1,159 @@
-
-use std::path::Path;
 use std::fs;
+use std::path::Path;
+use std::io::{self, Write};
+use crate::commands::copy::{self, Copier, CopyOptions, Dereference, LinkMode, Preserve, Reflink, Sparse, BASIC_ATTRIBUTES};
+use crate::commands::journal::{self, Op};
+use crate::commands::options::{Opt, Spec};
+use crate::commands::overwrite::Policy;
+
+static SPEC: Spec = Spec::new(
+    "cp",
+    "[OPTION]... SOURCE... DEST",
+    "Copy SOURCE to DEST, or multiple SOURCE(s) to DIRECTORY.",
+    &[
+        Opt::flag('a', "archive", "same as -R --preserve=all, copying symlinks as symlinks"),
+        Opt::optional("backup", "CONTROL", "make a backup of each existing destination file"),
+        Opt::flag('b', "", "like --backup but does not accept an argument"),
+        Opt::flag('d', "", "same as --no-dereference --preserve=links"),
+        Opt::flag('f', "force", "if an existing destination file cannot be opened, remove it and try again"),
+        Opt::flag('H', "", "follow command-line symbolic links in SOURCE"),
+        Opt::flag('i', "interactive", "prompt before overwrite (overrides a previous -n option)"),
+        Opt::flag('l', "link", "hard link files instead of copying"),
+        Opt::flag('L', "dereference", "always follow symbolic links in SOURCE"),
+        Opt::flag('n', "no-clobber", "do not overwrite an existing file (overrides a previous -i option)"),
+        Opt::flag('\0', "progress", "show a progress bar while copying each file"),
+        Opt::flag('P', "no-dereference", "never follow symbolic links in SOURCE"),
+        Opt::flag('p', "", "same as --preserve=mode,ownership,timestamps"),
+        Opt::optional("preserve", "ATTR_LIST", "preserve the specified attributes: mode, ownership, timestamps, xattr, links, all"),
+        Opt::flag('r', "recursive", "copy directories recursively"),
+        Opt::flag('R', "", "same as -r"),
+        Opt::optional("reflink", "WHEN", "clone file data where the filesystem allows: auto, always, never"),
+        Opt::flag('s', "symbolic-link", "make symbolic links instead of copying"),
+        Opt::value('\0', "sparse", "WHEN", "control creation of sparse files: auto, always, never"),
+        Opt::value('S', "suffix", "SUFFIX", "override the usual backup suffix"),
+        Opt::flag('u', "update", "copy only when the SOURCE file is newer than the destination file or when the destination file is missing"),
+        Opt::flag('v', "verbose", "explain what is being done"),
+    ],
+);
+
+pub fn cp(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let archive = matches.has("archive");
+    let recursive = archive || matches.has("recursive") || matches.has("R");
+
+    let mut preserve = if archive { Preserve::all() } else { Preserve::default() };
+    if matches.has("d") {
+        preserve.links = true;
+    }
+    if matches.has("p") {
+        preserve.add_list(BASIC_ATTRIBUTES).ok();
+    }
+    if matches.has("preserve") {
+        let list = matches.value("preserve").unwrap_or(BASIC_ATTRIBUTES);
+        if let Err(attr) = preserve.add_list(list) {
+            writeln!(err, "cp: invalid argument '{}' for '--preserve'", attr)?;
+            writeln!(err, "Valid arguments are: mode, ownership, timestamps, xattr, links, all")?;
+            return Ok(1);
+        }
+    }
+
+    let overwrite = match Policy::from_matches(&matches, false) {
+        Ok(policy) => policy,
+        Err(message) => {
+            writeln!(err, "cp: {}", message)?;
+            return Ok(1);
+        }
+    };
 
+    let reflink_arg = match matches.value("reflink") {
+        Some(arg) => arg,
+        // A bare --reflink means always, as in coreutils
+        None if matches.has("reflink") => "always",
+        None => "auto",
+    };
+    let reflink = match Reflink::parse(reflink_arg) {
+        Some(reflink) => reflink,
+        None => return invalid_argument(err, "reflink", reflink_arg),
+    };
+    let sparse_arg = matches.value("sparse").unwrap_or("auto");
+    let sparse = match Sparse::parse(sparse_arg) {
+        Some(sparse) => sparse,
+        None => return invalid_argument(err, "sparse", sparse_arg),
+    };
 
-pub fn cp(args: &[String]) -> String {
+    let options = CopyOptions {
+        recursive,
+        dereference: match matches.last_of(&["no-dereference", "d", "dereference", "H"]) {
+            Some("dereference") => Dereference::Always,
+            Some("H") => Dereference::CommandLine,
+            Some(_) => Dereference::Never,
+            // Recursive copies keep symlinks as symlinks
+            None if recursive => Dereference::Never,
+            None => Dereference::Always,
+        },
+        link: match matches.last_of(&["link", "symbolic-link"]) {
+            Some("link") => LinkMode::Hard,
+            Some("symbolic-link") => LinkMode::Symbolic,
+            _ => LinkMode::Copy,
+        },
+        preserve,
+        overwrite,
+        reflink,
+        sparse,
+        verbose: matches.has("verbose"),
+        progress: matches.has("progress"),
+    };
+
+    let args = &matches.operands;
     if args.len() < 2 {
-        return "cp: missing file operand".to_string();
+        writeln!(err, "cp: missing file operand")?;
+        return Ok(1);
     }
 
     let sources = &args[..args.len() - 1]; // all but last = sources
     let destination = &args[args.len() - 1]; // last = destination
     let dest_path = Path::new(destination);
+    let dest_is_dir = dest_path.is_dir();
 
     // If multiple sources, destination must be a directory
-    if sources.len() > 1 && (!dest_path.exists() || !dest_path.is_dir()) {
-        return format!("cp: target '{}' is not a directory", destination);
+    if sources.len() > 1 && !dest_is_dir {
+        writeln!(err, "cp: target '{}' is not a directory", destination)?;
+        return Ok(1);
     }
 
-    let mut messages = Vec::new();
+    let mut copier = Copier::new(&options, "cp", out, err);
 
     for source in sources {
         let src_path = Path::new(source);
-        let dest_file = if dest_path.is_dir() {
-            dest_path.join(src_path.file_name().unwrap())
+        let dest_file = if dest_is_dir {
+            match copy::target_name(src_path) {
+                Some(name) => dest_path.join(name),
+                None => {
+                    writeln!(copier.err(), "cp: cannot copy '{}': no file name to copy to", source)?;
+                    copier.failed = true;
+                    continue;
+                }
+            }
         } else {
             dest_path.to_path_buf()
         };
 
-        match fs::copy(src_path, &dest_file) {
-            Ok(_) => {}
-            Err(e) => messages.push(format!("cp: cannot copy '{}': {}", source, e)),
+        let existed = fs::symlink_metadata(&dest_file).is_ok();
+        copier.copy(src_path, &dest_file)?;
+        // Only what cp brought into being can be undone by deleting it
+        if !existed && fs::symlink_metadata(&dest_file).is_ok() {
+            journal::record(Op::Create { path: journal::absolute(&dest_file) });
         }
     }
 
-    if messages.is_empty() {
-        String::new()
-    } else {
-        messages.join("\n")
-    }
+    Ok(if copier.failed { 1 } else { 0 })
+}
+
+fn invalid_argument(err: &mut dyn Write, option: &str, arg: &str) -> io::Result<i32> {
+    writeln!(err, "cp: invalid argument '{}' for '--{}'", arg, option)?;
+    writeln!(err, "Valid arguments are: auto, always, never")?;
+    Ok(1)
 }
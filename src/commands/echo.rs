@@ -1,8 +1,44 @@
+use std::io::{self, Write};
+use crate::commands::options::{Opt, Spec};
+use crate::commands::printf::{unescape, Escapes};
 
-pub fn echo(input: &[String]) -> String {
-    if input.is_empty() {
-        println!();
-        return "".to_string();
-    } 
-    input.join(" ")
+static SPEC: Spec = Spec::new(
+    "echo",
+    "[OPTION]... [STRING]...",
+    "Display a line of text.\n\n\
+     With -e, these escapes are recognized:\n  \
+     \\\\ backslash  \\a alert  \\b backspace  \\c produce no further output\n  \
+     \\e escape  \\f form feed  \\n new line  \\r carriage return\n  \
+     \\t tab  \\v vertical tab  \\0NNN octal byte  \\xHH hex byte\n  \
+     \\uHHHH and \\UHHHHHHHH Unicode character",
+    &[
+        Opt::flag('n', "", "do not output the trailing newline"),
+        Opt::flag('e', "", "enable interpretation of backslash escapes"),
+        Opt::flag('E', "", "disable interpretation of backslash escapes (default)"),
+    ],
+)
+.literal();
+
+pub fn echo(input: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(input) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let text = matches.operands.join(" ");
+
+    if matches.last_of(&["e", "E"]) == Some("e") {
+        let (bytes, stop) = unescape(&text, Escapes::Echo);
+        out.write_all(&bytes)?;
+        // \c also swallows the trailing newline
+        if stop {
+            return Ok(0);
+        }
+    } else {
+        out.write_all(text.as_bytes())?;
+    }
+
+    if !matches.has("n") {
+        out.write_all(b"\n")?;
+    }
+    Ok(0)
 }
\ No newline at end of file
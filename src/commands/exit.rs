@@ -1,3 +1,15 @@
-pub fn exit()->String{
-     std::process::exit(0);
+use std::io::{self, Write};
+use crate::commands::options::Spec;
+
+static SPEC: Spec = Spec::new("exit", "", "Exit the shell.", &[]);
+
+pub fn exit(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    if let Err(e) = SPEC.parse(args) {
+        return e.report(out, err);
+    }
+
+    // Nothing runs after process::exit, so push out anything still buffered
+    out.flush()?;
+    err.flush()?;
+    std::process::exit(0);
 }
\ No newline at end of file
@@ -1,18 +1,29 @@
-use crate::commands::{ls::ls, rm::rm, mv::mv, cd::cd, cat::cat, echo::echo, exit::exit, cp::cp, pwd::pwd, mkdir::mkdir, clear::clear};
+use crate::commands::{ls::ls, rm::rm, mv::mv, cd::cd, cat::cat, echo::echo, exit::exit, cp::cp, pwd::pwd, mkdir::mkdir, clear::clear, trash::trash, journal::{journal, undo}, umask::umask, printf::printf};
+use std::io::{self, Write};
 
-pub fn handle_command(command: &str, input: &[String]) -> String {
+/// Dispatch a builtin. Output goes to `out`, diagnostics to `err`;
+/// the returned value is the command's exit status.
+pub fn handle_command(command: &str, input: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
     match command {
-        "echo" | r#""echo""# =>  echo(input),
-        "pwd" => pwd(),
-        "exit" => exit(),
-        "mkdir" => mkdir(input),
-        "clear" => clear(),
-        "cp" => cp(input),
-        "rm" => rm(input),
-        "mv" => mv(input),
-        "cat" => cat(input),
-        "ls" => ls(input),
-        "cd" => cd(input),
-        _ => format!("Command '{}' not found", command),
+        "echo" => echo(input, out, err),
+        "pwd" => pwd(input, out, err),
+        "exit" => exit(input, out, err),
+        "mkdir" => mkdir(input, out, err),
+        "clear" => clear(input, out, err),
+        "cp" => cp(input, out, err),
+        "rm" => rm(input, out, err),
+        "mv" => mv(input, out, err),
+        "cat" => cat(input, out, err),
+        "ls" => ls(input, out, err),
+        "cd" => cd(input, out, err),
+        "trash" => trash(input, out, err),
+        "journal" => journal(input, out, err),
+        "undo" => undo(input, out, err),
+        "umask" => umask(input, out, err),
+        "printf" => printf(input, out, err),
+        _ => {
+            writeln!(err, "Command '{}' not found", command)?;
+            Ok(127)
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,545 @@
+use chrono::{DateTime, Local};
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::commands::mv;
+use crate::commands::options::{Opt, Spec};
+use crate::commands::trash;
+
+static JOURNAL_SPEC: Spec = Spec::new(
+    "journal",
+    "[OPTION]...",
+    "Show the file operations recorded this session, newest last.",
+    &[Opt::value('n', "lines", "N", "show only the last N operations")],
+);
+
+static UNDO_SPEC: Spec = Spec::new(
+    "undo",
+    "[N]",
+    "Reverse the last N (default 1) journaled mv, cp, mkdir and rm --trash\n\
+     operations, newest first. Stops at any whose files have changed since.",
+    &[],
+);
+
+/// A file operation that can be reversed. Paths are absolute.
+pub enum Op {
+    /// `mv`, with where a replaced destination was backed up to
+    Move { from: PathBuf, to: PathBuf, backup: Option<PathBuf> },
+    /// `mv --exchange`
+    Exchange { a: PathBuf, b: PathBuf },
+    /// Something `cp` created
+    Create { path: PathBuf },
+    /// Every directory one `mkdir` made, parents before children
+    Mkdir { paths: Vec<PathBuf> },
+    /// `rm --trash`, with where the file went in the trash
+    Trash { original: PathBuf, trashed: PathBuf },
+}
+
+impl Op {
+    /// The paths whose state at record time undo insists on, and whether
+    /// everything under each counts too
+    fn watched(&self) -> Vec<(&Path, bool)> {
+        match self {
+            Op::Move { to, .. } => vec![(to, false)],
+            Op::Exchange { a, b } => vec![(a, false), (b, false)],
+            // Undo deletes what cp made, so nothing inside may have changed
+            Op::Create { path } => vec![(path, true)],
+            // What goes into a new directory is checked when undoing it
+            Op::Mkdir { paths } => paths.iter().map(|path| (path.as_path(), false)).collect(),
+            Op::Trash { trashed, .. } => vec![(trashed, false)],
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Op::Move { from, to, .. } => format!("mv '{}' -> '{}'", from.display(), to.display()),
+            Op::Exchange { a, b } => format!("mv --exchange '{}' '{}'", a.display(), b.display()),
+            Op::Create { path } => format!("cp -> '{}'", path.display()),
+            Op::Mkdir { paths } => {
+                let quoted: Vec<String> = paths.iter().map(|path| format!("'{}'", path.display())).collect();
+                format!("mkdir {}", quoted.join(" "))
+            }
+            Op::Trash { original, .. } => format!("rm --trash '{}'", original.display()),
+        }
+    }
+}
+
+/// What a file looked like: its inode, and a digest of its size and
+/// modification time. A directory is known by its inode alone, as undoing
+/// one step changes its size and mtime, unless the stamp is deep; then the
+/// digest covers the names, inodes, sizes and mtimes of everything inside.
+#[derive(PartialEq, Eq, Debug)]
+struct Stamp {
+    ino: u64,
+    digest: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path, deep: bool) -> Option<Stamp> {
+        let meta = fs::symlink_metadata(path).ok()?;
+        let mut hasher = DefaultHasher::new();
+        if !meta.is_dir() {
+            (meta.len(), meta.mtime(), meta.mtime_nsec()).hash(&mut hasher);
+        } else if deep {
+            hash_tree(path, &mut hasher).ok()?;
+        }
+        Some(Stamp { ino: meta.ino(), digest: hasher.finish() })
+    }
+
+    fn encode(&self) -> String {
+        format!("{}:{:x}", self.ino, self.digest)
+    }
+
+    fn decode(text: &str) -> Option<Stamp> {
+        let (ino, digest) = text.split_once(':')?;
+        Some(Stamp { ino: ino.parse().ok()?, digest: u64::from_str_radix(digest, 16).ok()? })
+    }
+}
+
+/// Feed everything under `dir` to `hasher`, in name order
+fn hash_tree(dir: &Path, hasher: &mut DefaultHasher) -> io::Result<()> {
+    let mut names = fs::read_dir(dir)?.map(|entry| entry.map(|e| e.file_name())).collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    for name in names {
+        let path = dir.join(&name);
+        let meta = fs::symlink_metadata(&path)?;
+        name.as_bytes().hash(hasher);
+        meta.ino().hash(hasher);
+        if meta.is_dir() {
+            // Bracket the contents so moving a file up a level shows
+            b'/'.hash(hasher);
+            hash_tree(&path, hasher)?;
+            b'.'.hash(hasher);
+        } else {
+            (meta.len(), meta.mtime(), meta.mtime_nsec()).hash(hasher);
+        }
+    }
+    Ok(())
+}
+
+struct Entry {
+    time: i64,
+    op: Op,
+    /// One per watched path, `None` where it couldn't be taken
+    stamps: Vec<Option<Stamp>>,
+}
+
+/// Note a completed operation in this session's journal. Journaling is
+/// best effort: a journal that can't be written never fails the command.
+pub fn record(op: Op) {
+    if let Some(journal) = journal_path() {
+        record_in(&journal, op);
+    }
+}
+
+fn record_in(journal: &Path, op: Op) {
+    let stamps = op.watched().into_iter().map(|(path, deep)| Stamp::of(path, deep)).collect();
+    let entry = Entry { time: now(), op, stamps };
+    if let Some(dir) = journal.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(journal) {
+        let _ = writeln!(file, "{}", encode_entry(&entry));
+    }
+}
+
+/// `path` made absolute against the current directory, without resolving
+/// anything, so it still names the same place after a `cd`
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub fn journal(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match JOURNAL_SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    if let Some(extra) = matches.operands.first() {
+        writeln!(err, "journal: extra operand '{}'", extra)?;
+        return Ok(2);
+    }
+    let entries = load(journal_path().as_deref());
+    let count = match matches.value("lines").map(str::parse::<usize>) {
+        None => entries.len(),
+        Some(Ok(n)) => n.min(entries.len()),
+        Some(Err(_)) => {
+            writeln!(err, "journal: invalid number of lines: '{}'", matches.value("lines").unwrap_or_default())?;
+            return Ok(1);
+        }
+    };
+
+    let first = entries.len() - count;
+    for (i, entry) in entries.iter().enumerate().skip(first) {
+        let time = DateTime::from_timestamp(entry.time, 0)
+            .map(|t| t.with_timezone(&Local).format("%H:%M:%S").to_string())
+            .unwrap_or_default();
+        writeln!(out, "{:>5}  {}  {}", i + 1, time, entry.op.describe())?;
+    }
+    Ok(0)
+}
+
+pub fn undo(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    undo_in(journal_path().as_deref(), args, out, err)
+}
+
+fn undo_in(journal: Option<&Path>, args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match UNDO_SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let count = match matches.operands.as_slice() {
+        [] => 1,
+        [n] => match n.parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => {
+                writeln!(err, "undo: invalid count '{}'", n)?;
+                return Ok(1);
+            }
+        },
+        [_, extra, ..] => {
+            writeln!(err, "undo: extra operand '{}'", extra)?;
+            return Ok(2);
+        }
+    };
+
+    let mut entries = load(journal);
+    if entries.is_empty() {
+        writeln!(err, "undo: nothing to undo")?;
+        return Ok(1);
+    }
+
+    let mut status = 0;
+    for _ in 0..count {
+        let entry = match entries.pop() {
+            Some(entry) => entry,
+            None => break,
+        };
+        if let Err(reason) = reverse(&entry, out, err)? {
+            writeln!(err, "undo: cannot undo {}: {}", entry.op.describe(), reason)?;
+            entries.push(entry);
+            status = 1;
+            break;
+        }
+        writeln!(out, "undone: {}", entry.op.describe())?;
+    }
+
+    if let Err(e) = save(journal, &entries) {
+        writeln!(err, "undo: cannot update the journal: {}", e)?;
+        status = 1;
+    }
+    Ok(status)
+}
+
+/// Reverse one operation, or say why not
+fn reverse(entry: &Entry, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<Result<(), String>> {
+    for (i, (path, deep)) in entry.op.watched().into_iter().enumerate() {
+        let recorded = entry.stamps.get(i).and_then(Option::as_ref);
+        if recorded.is_none() || Stamp::of(path, deep).as_ref() != recorded {
+            return Ok(Err(format!("'{}' has changed since", path.display())));
+        }
+    }
+    let free = |path: &Path| {
+        if fs::symlink_metadata(path).is_ok() {
+            Err(format!("'{}' exists again", path.display()))
+        } else {
+            Ok(())
+        }
+    };
+
+    let result = match &entry.op {
+        Op::Move { from, to, backup } => {
+            if let Err(reason) = free(from) {
+                return Ok(Err(reason));
+            }
+            match fs::rename(to, from) {
+                Err(ref e) if e.raw_os_error() == Some(libc::EXDEV) => {
+                    if !mv::move_across(to, from, libc::RENAME_NOREPLACE, out, err)? {
+                        return Ok(Err("moving it back failed".to_string()));
+                    }
+                    Ok(())
+                }
+                moved => moved,
+            }
+            .and_then(|_| match backup {
+                Some(backup) => fs::rename(backup, to),
+                None => Ok(()),
+            })
+        }
+        Op::Exchange { a, b } => mv::rename_with(a, b, libc::RENAME_EXCHANGE),
+        Op::Create { path } => match fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+            _ => fs::remove_file(path),
+        },
+        Op::Mkdir { paths } => {
+            // Anything else inside stops undo before any directory goes
+            let foreign = paths.iter().find_map(|dir| {
+                let mut entries = fs::read_dir(dir).ok()?;
+                entries.find_map(|e| e.ok().map(|e| e.path()).filter(|p| !paths.contains(p)))
+            });
+            if let Some(foreign) = foreign {
+                return Ok(Err(format!("'{}' was added since", foreign.display())));
+            }
+            paths.iter().rev().try_for_each(fs::remove_dir)
+        }
+        Op::Trash { original, trashed } => {
+            if let Err(reason) = free(original) {
+                return Ok(Err(reason));
+            }
+            trash::untrash(trashed, original)
+        }
+    };
+    Ok(result.map_err(|e| e.to_string()))
+}
+
+/// One file per session under `$XDG_STATE_HOME/0shell`, by default
+/// `~/.local/state/0shell`
+fn journal_path() -> Option<PathBuf> {
+    static SESSION: OnceLock<String> = OnceLock::new();
+    let session = SESSION.get_or_init(|| format!("{}-{}", now(), process::id()));
+
+    let state = match env::var_os("XDG_STATE_HOME") {
+        Some(state) if !state.is_empty() => PathBuf::from(state),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(state.join("0shell").join(format!("journal-{}", session)))
+}
+
+fn load(journal: Option<&Path>) -> Vec<Entry> {
+    let text = journal.and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+    text.lines().filter_map(decode_entry).collect()
+}
+
+fn save(journal: Option<&Path>, entries: &[Entry]) -> io::Result<()> {
+    let path = match journal {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let text: String = entries.iter().map(|entry| encode_entry(entry) + "\n").collect();
+    fs::write(path, text)
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// A journal line: time, stamps (comma-separated, `-` for none), kind,
+/// then paths, separated by tabs
+fn encode_entry(entry: &Entry) -> String {
+    let (kind, paths): (&str, Vec<&Path>) = match &entry.op {
+        Op::Move { from, to, backup: None } => ("move", vec![from, to]),
+        Op::Move { from, to, backup: Some(backup) } => ("move", vec![from, to, backup]),
+        Op::Exchange { a, b } => ("exchange", vec![a, b]),
+        Op::Create { path } => ("create", vec![path]),
+        Op::Mkdir { paths } => ("mkdir", paths.iter().map(PathBuf::as_path).collect()),
+        Op::Trash { original, trashed } => ("trash", vec![original, trashed]),
+    };
+    let stamps: Vec<String> = entry.stamps.iter().map(|stamp| stamp.as_ref().map(Stamp::encode).unwrap_or_else(|| "-".to_string())).collect();
+    let mut fields = vec![entry.time.to_string(), stamps.join(","), kind.to_string()];
+    fields.extend(paths.iter().map(|path| escape(path.as_os_str().as_bytes())));
+    fields.join("\t")
+}
+
+fn decode_entry(line: &str) -> Option<Entry> {
+    let mut fields = line.split('\t');
+    let time = fields.next()?.parse().ok()?;
+    let stamps = fields.next()?.split(',').map(Stamp::decode).collect();
+    let kind = fields.next()?;
+    let paths: Vec<PathBuf> = fields.map(|field| PathBuf::from(OsString::from_vec(unescape(field)))).collect();
+
+    let op = match (kind, paths.as_slice()) {
+        ("move", [from, to]) => Op::Move { from: from.clone(), to: to.clone(), backup: None },
+        ("move", [from, to, backup]) => Op::Move { from: from.clone(), to: to.clone(), backup: Some(backup.clone()) },
+        ("exchange", [a, b]) => Op::Exchange { a: a.clone(), b: b.clone() },
+        ("create", [path]) => Op::Create { path: path.clone() },
+        ("mkdir", [_, ..]) => Op::Mkdir { paths: paths.clone() },
+        ("trash", [original, trashed]) => Op::Trash { original: original.clone(), trashed: trashed.clone() },
+        _ => return None,
+    };
+    Some(Entry { time, op, stamps })
+}
+
+/// Keep tabs and newlines in paths from breaking up journal lines, and
+/// bytes that aren't UTF-8 intact as `\xHH`
+fn escape(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        match bytes.get(i + 1) {
+            Some(b't') => out.push(b'\t'),
+            Some(b'n') => out.push(b'\n'),
+            Some(b'x') => {
+                let hex = text.get(i + 2..i + 4).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(b) = hex {
+                    out.push(b);
+                    i += 4;
+                    continue;
+                }
+                out.push(b'x');
+            }
+            Some(&other) => out.push(other),
+            None => out.push(b'\\'),
+        }
+        i += 2;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping_round_trips() {
+        let names: [&[u8]; 5] = [b"plain", b"tab\there", b"new\nline", b"back\\slash\\x41", b"latin1 \xe9t\xe9 \xff"];
+        for name in names {
+            let escaped = escape(name);
+            assert!(!escaped.contains('\t') && !escaped.contains('\n'));
+            assert_eq!(unescape(&escaped), name);
+        }
+        assert_eq!(escape("é".as_bytes()), "é");
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let odd = PathBuf::from(OsString::from_vec(b"/tmp/\xff\tname".to_vec()));
+        let entry = Entry {
+            time: 42,
+            op: Op::Move { from: odd.clone(), to: PathBuf::from("/tmp/b"), backup: Some(PathBuf::from("/tmp/b~")) },
+            stamps: vec![Some(Stamp { ino: 7, digest: 0xdead }), None],
+        };
+        let decoded = decode_entry(&encode_entry(&entry)).unwrap();
+        assert_eq!(decoded.time, 42);
+        assert_eq!(decoded.stamps, entry.stamps);
+        match decoded.op {
+            Op::Move { from, to, backup } => {
+                assert_eq!(from, odd);
+                assert_eq!(to, PathBuf::from("/tmp/b"));
+                assert_eq!(backup, Some(PathBuf::from("/tmp/b~")));
+            }
+            _ => panic!("decoded to a different operation"),
+        }
+    }
+
+    /// A scratch directory and a journal of its own for one test
+    fn scratch(name: &str) -> (PathBuf, PathBuf) {
+        let root = env::temp_dir().join(format!("0shell-journal-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let journal = root.join("journal");
+        (root, journal)
+    }
+
+    fn run_undo(journal: &Path, args: &[&str]) -> (i32, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let status = undo_in(Some(journal), &args, &mut out, &mut err).unwrap();
+        (status, String::from_utf8_lossy(&err).into_owned())
+    }
+
+    #[test]
+    fn chained_directory_moves() {
+        let (root, journal) = scratch("moves");
+        fs::create_dir_all(root.join("dst")).unwrap();
+        fs::create_dir_all(root.join("arch")).unwrap();
+
+        // Undoing a move into a moved directory must not spoil undoing the
+        // move of the directory itself
+        fs::rename(root.join("dst"), root.join("dst2")).unwrap();
+        record_in(&journal, Op::Move { from: root.join("dst"), to: root.join("dst2"), backup: None });
+        fs::rename(root.join("arch"), root.join("dst2/arch")).unwrap();
+        record_in(&journal, Op::Move { from: root.join("arch"), to: root.join("dst2/arch"), backup: None });
+        assert_eq!(run_undo(&journal, &["2"]), (0, String::new()));
+        assert!(root.join("dst").is_dir() && root.join("arch").is_dir());
+        assert!(!root.join("dst2").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn changed_copies_are_kept() {
+        let (root, journal) = scratch("copy");
+        fs::create_dir_all(root.join("copy/sub")).unwrap();
+        fs::write(root.join("copy/sub/f"), "copied").unwrap();
+        record_in(&journal, Op::Create { path: root.join("copy") });
+        OpenOptions::new().append(true).open(root.join("copy/sub/f")).unwrap().write_all(b" and edited").unwrap();
+        let (status, message) = run_undo(&journal, &[]);
+        assert_eq!(status, 1);
+        assert!(message.contains("has changed since"));
+        assert!(root.join("copy/sub/f").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn mkdir_parents_undo_together() {
+        let (root, journal) = scratch("mkdir");
+        let paths = vec![root.join("a"), root.join("a/b"), root.join("a/b/c")];
+        for path in &paths {
+            fs::create_dir(path).unwrap();
+        }
+        record_in(&journal, Op::Mkdir { paths: paths.clone() });
+
+        // Something else inside keeps all of them
+        fs::write(root.join("a/b/note"), "").unwrap();
+        let (status, message) = run_undo(&journal, &[]);
+        assert_eq!(status, 1);
+        assert!(message.contains("was added since"));
+        assert!(root.join("a/b/c").is_dir());
+
+        fs::remove_file(root.join("a/b/note")).unwrap();
+        assert_eq!(run_undo(&journal, &[]), (0, String::new()));
+        assert!(!root.join("a").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn exchanged_paths_are_checked() {
+        let (root, journal) = scratch("exchange");
+        fs::write(root.join("x"), "x").unwrap();
+        fs::write(root.join("y"), "y").unwrap();
+        record_in(&journal, Op::Exchange { a: root.join("x"), b: root.join("y") });
+        fs::write(root.join("y"), "replaced").unwrap();
+        let (status, message) = run_undo(&journal, &[]);
+        assert_eq!(status, 1);
+        assert!(message.contains("has changed since"));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
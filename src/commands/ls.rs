@@ -1,198 +1,532 @@
-use chrono::{Datelike, TimeZone};
+use std::env;
 use std::fs;
+use std::io::{self, Write};
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
-use std::path::Path;
-use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
 use users::{get_group_by_gid, get_user_by_uid};
+use crate::commands::options::{Opt, Spec};
+use crate::commands::size::{self, Scale};
+use crate::commands::xattr;
+
+mod color;
+mod json;
+mod layout;
+mod sort;
+mod time;
+
+use self::color::{Colors, When};
+use self::json::JsonFormat;
+use self::layout::Layout;
+use self::sort::{SortKey, SortOptions, TimeField};
+use self::time::TimeStyle;
+
+static SPEC: Spec = Spec::new(
+    "ls",
+    "[OPTION]... [FILE]...",
+    "List information about the FILEs (the current directory by default).",
+    &[
+        Opt::flag('a', "all", "do not ignore entries starting with ."),
+        Opt::value('\0', "block-size", "SIZE", "with -l and -s, scale sizes by SIZE (K, M, G, KB, 1024...)"),
+        Opt::flag('c', "", "with -l: show ctime; with -t: sort by ctime"),
+        Opt::flag('C', "", "list entries by columns"),
+        Opt::optional("color", "WHEN", "colorize the output; WHEN can be 'always', 'auto', or 'never'"),
+        Opt::flag('d', "directory", "list directories themselves, not their contents"),
+        Opt::flag('F', "classify", "append indicator (one of */=@|) to entries"),
+        Opt::flag('g', "", "like -l, but do not list owner"),
+        Opt::flag('H', "dereference-command-line", "follow symbolic links listed on the command line"),
+        Opt::flag('\0', "full-time", "like -l --time-style=full-iso"),
+        Opt::flag('\0', "group-directories-first", "group directories before files"),
+        Opt::flag('h', "human-readable", "with -l and -s, print sizes like 1K 234M 2G etc."),
+        Opt::flag('\0', "si", "likewise, but use powers of 1000 not 1024"),
+        Opt::flag('i', "inode", "print the index number of each file"),
+        Opt::flag('\0', "json", "print entries as a JSON array of objects"),
+        Opt::flag('l', "", "use a long listing format"),
+        Opt::flag('L', "dereference", "show information for the file symbolic links point to"),
+        Opt::flag('n', "numeric-uid-gid", "like -l, but list numeric user and group IDs"),
+        Opt::flag('\0', "ndjson", "print entries as JSON objects, one per line"),
+        Opt::flag('o', "", "like -l, but do not list group information"),
+        Opt::flag('r', "reverse", "reverse order while sorting"),
+        Opt::flag('R', "recursive", "list subdirectories recursively"),
+        Opt::flag('s', "size", "print the allocated size of each file, in blocks"),
+        Opt::flag('S', "", "sort by file size, largest first"),
+        Opt::flag('t', "", "sort by time, newest first; see --time"),
+        Opt::value('\0', "time", "WORD", "time to show and sort by: atime, ctime, birth (default mtime)"),
+        Opt::value('\0', "time-style", "STYLE", "full-iso, long-iso, iso, locale, or +FORMAT"),
+        Opt::flag('u', "", "with -l: show access time; with -t: sort by access time"),
+        Opt::flag('U', "", "do not sort; list entries in directory order"),
+        Opt::flag('v', "", "natural sort of (version) numbers within text"),
+        Opt::value('w', "width", "COLS", "set output width to COLS.  0 means no limit"),
+        Opt::flag('x', "", "list entries by lines instead of by columns"),
+        Opt::flag('X', "", "sort alphabetically by entry extension"),
+        Opt::flag('1', "", "list one file per line"),
+        Opt::flag('@', "xattr", "list extended attribute names and values below each file"),
+    ],
+);
+
+/// Everything the command line decided about how to list
+struct Config {
+    show_all: bool,
+    classify: bool,
+    long_format: bool,
+    layout: Layout,
+    width: usize,
+    sort: SortOptions,
+    recursive: bool,
+    directory: bool,
+    show_inode: bool,
+    show_blocks: bool,
+    /// Scale for the size column of -l
+    size_scale: Scale,
+    /// Scale for -s and the "total" line, which count 512-byte blocks
+    block_scale: Scale,
+    numeric_ids: bool,
+    show_owner: bool,
+    show_group: bool,
+    colors: Option<Colors>,
+    show_xattrs: bool,
+    time_style: TimeStyle,
+    /// "Now" in seconds, fixed for the whole listing
+    now: i64,
+    /// -L: follow every symlink
+    dereference: bool,
+    /// -H, or implied for links to directories in the plain short format
+    dereference_args: bool,
+    json: Option<JsonFormat>,
+}
 
+type Item = (String, PathBuf, fs::Metadata);
 
+impl Config {
+    /// Colour a name if --color is in effect
+    fn paint(&self, text: &str, path: &Path, meta: &fs::Metadata) -> String {
+        match &self.colors {
+            Some(colors) => colors.paint(text, path, meta),
+            None => text.to_string(),
+        }
+    }
 
+    /// Colour the target of a broken symlink
+    fn paint_missing(&self, text: &str) -> String {
+        match &self.colors {
+            Some(colors) => colors.paint_missing(text),
+            None => text.to_string(),
+        }
+    }
 
-// /// Look up username for a given UID by parsing /etc/passwd
-// fn user_name(uid: u32) -> String {
-//     if let Ok(content) = fs::read_to_string("/etc/passwd") {
-//         for line in content.lines() {
-//             if line.starts_with('#') || line.trim().is_empty() {
-//                 continue;
-//             }
-//             let parts: Vec<&str> = line.split(':').collect();
-//             if parts.len() >= 3 {
-//                 if let Ok(id) = parts[2].parse::<u32>() {
-//                     if id == uid {
-//                         return parts[0].to_string(); // username
-//                     }
-//                 }
-//             }
-//         }
-//     }
-//     uid.to_string() // fallback: show UID if not found
-// }
-
-// /// Look up group name for a given GID by parsing /etc/group
-// fn group_name(gid: u32) -> String {
-//     if let Ok(content) = fs::read_to_string("/etc/group") {
-//         for line in content.lines() {
-//             if line.starts_with('#') || line.trim().is_empty() {
-//                 continue;
-//             }
-//             let parts: Vec<&str> = line.split(':').collect();
-//             if parts.len() >= 3 {
-//                 if let Ok(id) = parts[2].parse::<u32>() {
-//                     if id == gid {
-//                         return parts[0].to_string(); // group name
-//                     }
-//                 }
-//             }
-//         }
-//     }
-//     gid.to_string() // fallback: show GID if not found
-// }
-
-pub fn ls(args: &[String]) -> String {
-    let mut output = String::new();
-    let mut show_all = false;
-    let mut long_format = false;
-    let mut classify = false;
-
-    let mut targets: Vec<&str> = Vec::new();
-
-    // Parse arguments
-    for arg in args {
-        if arg.starts_with('-') {
-            for ch in arg.chars().skip(1) {
-                match ch {
-                    'a' => show_all = true,
-                    'l' => long_format = true,
-                    'F' => classify = true,
-                    _ => output.push_str(&format!("ls: invalid option -- '{}'\n", ch)),
-                }
+    /// Metadata for an entry, following symlinks when asked to. A broken
+    /// link is still listed, as itself.
+    fn metadata(&self, path: &Path, follow: bool) -> io::Result<fs::Metadata> {
+        if follow {
+            if let Ok(meta) = fs::metadata(path) {
+                return Ok(meta);
             }
-        } else {
-            targets.push(arg.as_str());
         }
+        fs::symlink_metadata(path)
     }
+}
+
+pub fn ls(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+
+    // -l, -1, -C and -x override each other; without any of them we use
+    // columns on a terminal and one name per line otherwise
+    let format = match matches.last_of(&["l", "g", "o", "numeric-uid-gid", "full-time", "1", "C", "x"]) {
+        // -g, -o, -n and --full-time are variants of -l
+        Some("g") | Some("o") | Some("numeric-uid-gid") | Some("full-time") => Some("l"),
+        other => other,
+    };
+    let layout = match format {
+        Some("C") => Layout::Vertical,
+        Some("x") => Layout::Horizontal,
+        Some(_) => Layout::OnePerLine,
+        None if layout::stdout_is_tty() => Layout::Vertical,
+        None => Layout::OnePerLine,
+    };
+    let requested_width = match matches.value("width") {
+        Some(w) => match w.parse::<usize>() {
+            Ok(w) => Some(w),
+            Err(_) => {
+                writeln!(err, "ls: invalid line width: '{}'", w)?;
+                return Ok(2);
+            }
+        },
+        None => None,
+    };
+
+    let time = match matches.last_of(&["u", "c", "time"]) {
+        Some("u") => TimeField::Accessed,
+        Some("c") => TimeField::Changed,
+        Some(_) => {
+            let word = matches.value("time").unwrap_or_default();
+            match TimeField::parse(word) {
+                Some(t) => t,
+                None => {
+                    writeln!(err, "ls: invalid argument '{}' for '--time'", word)?;
+                    writeln!(err, "Valid arguments are: atime, access, use, ctime, status, birth, creation, mtime")?;
+                    return Ok(2);
+                }
+            }
+        }
+        None => TimeField::Modified,
+    };
+
+    // --full-time and --time-style override each other; $TIME_STYLE is the default
+    let style = match matches.last_of(&["full-time", "time-style"]) {
+        Some("full-time") => Some("full-iso".to_string()),
+        Some(_) => matches.value("time-style").map(|s| s.to_string()),
+        None => env::var("TIME_STYLE").ok(),
+    };
+    let time_style = match style {
+        Some(style) => match TimeStyle::parse(&style) {
+            Some(t) => t,
+            None => {
+                writeln!(err, "ls: invalid time style format '{}'", style)?;
+                writeln!(err, "Valid arguments are: full-iso, long-iso, iso, locale, +FORMAT")?;
+                return Ok(2);
+            }
+        },
+        None => TimeStyle::Locale,
+    };
+    let sort = SortOptions {
+        key: match matches.last_of(&["t", "S", "X", "v", "U"]) {
+            Some("t") => SortKey::Time,
+            Some("S") => SortKey::Size,
+            Some("X") => SortKey::Extension,
+            Some("v") => SortKey::Version,
+            Some("U") => SortKey::Unsorted,
+            // -u, -c and --time with no sort given and no -l to show the
+            // time in sort by it instead, as in GNU ls
+            None if format != Some("l") && matches.last_of(&["u", "c", "time"]).is_some() => SortKey::Time,
+            _ => SortKey::Name,
+        },
+        time,
+        reverse: matches.has("reverse"),
+        dirs_first: matches.has("group-directories-first"),
+    };
+
+    // -h/--si and --block-size override each other
+    let (size_scale, block_scale) = match matches.last_of(&["human-readable", "si", "block-size"]) {
+        Some("human-readable") => (Scale::Human { base: 1024 }, Scale::Human { base: 1024 }),
+        Some("si") => (Scale::Human { base: 1000 }, Scale::Human { base: 1000 }),
+        Some(_) => {
+            let arg = matches.value("block-size").unwrap_or_default();
+            match size::parse_block_size(arg) {
+                Some(scale) => (scale.clone(), scale),
+                None => {
+                    writeln!(err, "ls: invalid --block-size argument '{}'", arg)?;
+                    return Ok(2);
+                }
+            }
+        }
+        None => (Scale::bytes(), Scale::Blocks { size: 1024, suffix: String::new() }),
+    };
+
+    let colors = match When::parse(matches.value("color")) {
+        _ if !matches.has("color") => None,
+        Some(When::Always) => Some(Colors::from_env()),
+        Some(When::Auto) if layout::stdout_is_tty() => Some(Colors::from_env()),
+        Some(_) => None,
+        None => {
+            writeln!(err, "ls: invalid argument '{}' for '--color'", matches.value("color").unwrap_or_default())?;
+            writeln!(err, "Valid arguments are: always, yes, force, never, no, none, auto, tty, if-tty")?;
+            return Ok(2);
+        }
+    };
 
+    let config = Config {
+        show_all: matches.has("all"),
+        classify: matches.has("classify"),
+        long_format: format == Some("l"),
+        layout,
+        width: layout::line_width(requested_width),
+        sort,
+        recursive: matches.has("recursive"),
+        directory: matches.has("directory"),
+        show_inode: matches.has("inode"),
+        show_blocks: matches.has("size"),
+        size_scale,
+        block_scale,
+        numeric_ids: matches.has("numeric-uid-gid"),
+        show_owner: !matches.has("g"),
+        show_group: !matches.has("o"),
+        colors,
+        show_xattrs: matches.has("xattr"),
+        time_style,
+        now: chrono::Local::now().timestamp(),
+        dereference: matches.has("dereference"),
+        dereference_args: matches.has("dereference") || matches.has("dereference-command-line"),
+        json: match matches.last_of(&["json", "ndjson"]) {
+            Some("json") => Some(JsonFormat::Array),
+            Some(_) => Some(JsonFormat::Lines),
+            None => None,
+        },
+    };
+    // Like coreutils, a plain `ls link-to-dir` lists the directory
+    let follow_dir_links = !config.dereference_args && !config.directory && !config.long_format && !config.classify;
+
+    let mut targets: Vec<&str> = matches.operands.iter().map(|s| s.as_str()).collect();
     if targets.is_empty() {
         targets.push(".");
     }
 
-    fn format_name(name: &str) -> String {
-        if name.contains(' ') || name.chars().any(|c: char| !c.is_alphanumeric() && c != '.' && c != '_' && c != '-' && c != '@' && c != '/'  ) {
-            format!("'{}'", name)
-        } else {
-            name.to_string()
-        }
+    let mut lister = Lister { config: &config, out, err, status: 0, printed: false, json_count: 0 };
+    if config.json == Some(JsonFormat::Array) {
+        write!(lister.out, "[")?;
     }
 
+    // Like coreutils: plain files first as one group, then each directory
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
     for target in targets.iter() {
-        let path = Path::new(target);
+        let path = PathBuf::from(target);
+        let meta = config.metadata(&path, config.dereference_args).and_then(|meta| {
+            if follow_dir_links && meta.file_type().is_symlink() && path.is_dir() {
+                fs::metadata(&path)
+            } else {
+                Ok(meta)
+            }
+        });
+        match meta {
+            Ok(meta) => {
+                if config.directory || !meta.is_dir() {
+                    files.push((target.to_string(), path, meta));
+                } else {
+                    dirs.push((target.to_string(), path, meta));
+                }
+            }
+            Err(e) => {
+                writeln!(lister.err, "ls: cannot access '{}': {}", target, e)?;
+                lister.status = 2;
+            }
+        }
+    }
+
+    if !files.is_empty() {
+        let files = sort::sort_entries(files, &config.sort);
+        lister.write_entries(&files, false)?;
+    }
+
+    // A header is needed as soon as there is more than one thing to show
+    let headers = config.recursive || targets.len() > 1;
+    for (name, path, meta) in sort::sort_entries(dirs, &config.sort) {
+        let mut ancestors = vec![(meta.dev(), meta.ino())];
+        lister.list_dir(&name, &path, headers, &mut ancestors, true)?;
+    }
+
+    if config.json == Some(JsonFormat::Array) {
+        writeln!(lister.out, "{}]", if lister.json_count > 0 { "\n" } else { "" })?;
+    }
+
+    Ok(lister.status)
+}
+
+fn format_name(name: &str) -> String {
+    if name.contains(' ') || name.chars().any(|c: char| !c.is_alphanumeric() && c != '.' && c != '_' && c != '-' && c != '@' && c != '/'  ) {
+        format!("'{}'", name)
+    } else {
+        name.to_string()
+    }
+}
+
+struct Lister<'a> {
+    config: &'a Config,
+    out: &'a mut dyn Write,
+    err: &'a mut dyn Write,
+    status: i32,
+    /// Whether anything has been listed yet, so groups get a blank line between them
+    printed: bool,
+    /// Objects written so far in --json mode
+    json_count: usize,
+}
+
+impl Lister<'_> {
+    /// List one directory and, with -R, everything below it. `ancestors`
+    /// holds the (dev, inode) of every directory on the current path.
+    fn list_dir(&mut self, name: &str, path: &Path, header: bool, ancestors: &mut Vec<(u64, u64)>, top_level: bool) -> io::Result<()> {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                writeln!(self.err, "ls: cannot open directory '{}': {}", name, e)?;
+                // Failing on a command-line argument is serious; deeper down it's minor
+                self.status = if top_level { 2 } else { self.status.max(1) };
+                return Ok(());
+            }
+        };
 
-        if targets.len() > 1 {
-            output.push_str(&format!("{}:\n", format_name(target)));
+        if header && self.config.json.is_none() {
+            if self.printed {
+                writeln!(self.out)?;
+            }
+            writeln!(self.out, "{}:", format_name(name))?;
         }
+        self.printed = true;
 
-        // Handle single file or symlink
-        if path.is_file() || path.is_symlink() {
-            if let Ok(meta) = fs::symlink_metadata(path) {
-                let display_name = if classify {
-                    format!("{}{}", target, suffix_for(path, &meta))
-                } else {
-                    target.to_string()
-                };
-                let display_name = format_name(&display_name);
-                if long_format {
-                    output.push_str(&format!("{}\n", long_format_line(path, &meta, &display_name)));
-                } else {
-                    output.push_str(&format!("{}\n", display_name));
+        let mut items: Vec<Item> = Vec::new();
+
+        if self.config.show_all {
+            for name in &[".", ".."] {
+                let p = path.join(name);
+                if let Ok(meta) = self.config.metadata(&p, self.config.dereference) {
+                    items.push((name.to_string(), p, meta));
                 }
-            } else {
-                output.push_str(&format!("{}\n", format_name(target)));
             }
-            continue;
         }
 
-        // Handle directory
-        match fs::read_dir(path) {
-            Ok(entries) => {
-                let mut items: Vec<(String, std::path::PathBuf, fs::Metadata)> = Vec::new();
-
-                if show_all {
-                    for name in &[".", ".."] {
-                        let p = path.join(name);
-                        if let Ok(meta) = fs::symlink_metadata(&p) {
-                            items.push((name.to_string(), p, meta));
-                        }
-                    }
-                }
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !self.config.show_all && name.starts_with('.') {
+                continue;
+            }
+            let item_path = entry.path();
+            if let Ok(meta) = self.config.metadata(&item_path, self.config.dereference) {
+                items.push((name, item_path, meta));
+            }
+        }
 
-                for entry in entries.flatten() {
-                    let name = entry.file_name().to_string_lossy().to_string();
-                    if !show_all && name.starts_with('.') {
-                        continue;
-                    }
-                    let item_path = entry.path();
-                    if let Ok(meta) = fs::symlink_metadata(&item_path) {
-                        items.push((name, item_path, meta));
-                    }
-                }
+        let items = sort::sort_entries(items, &self.config.sort);
+        self.write_entries(&items, true)?;
+
+        if !self.config.recursive {
+            return Ok(());
+        }
 
-                items.sort_by(|a, b| ls_cmp(&a.0, &b.0));
+        for (entry_name, entry_path, meta) in &items {
+            if !meta.is_dir() || entry_name == "." || entry_name == ".." {
+                continue;
+            }
+            let child = format!("{}/{}", name.trim_end_matches('/'), entry_name);
+            let id = (meta.dev(), meta.ino());
+            if ancestors.contains(&id) {
+                writeln!(self.err, "ls: {}: not listing already-listed directory", format_name(&child))?;
+                self.status = self.status.max(1);
+                continue;
+            }
+            ancestors.push(id);
+            self.list_dir(&child, entry_path, true, ancestors, false)?;
+            ancestors.pop();
+        }
 
-                if long_format {
-                    let total_blocks: u64 = items.iter().map(|(_, _, m)| m.blocks() as u64).sum();
-                    output.push_str(&format!("total {}\n", (total_blocks + 1) / 2));
+        Ok(())
+    }
+
+    /// Print a group of entries in the chosen format. `in_dir` is false for
+    /// file operands, which show their path as given and get no total line.
+    fn write_entries(&mut self, items: &[Item], in_dir: bool) -> io::Result<()> {
+        let config = self.config;
+        self.printed = true;
+
+        // Every entry, from every directory, goes into the same JSON stream
+        if let Some(format) = config.json {
+            for (name, path, meta) in items {
+                let object = json::entry(name, path, meta);
+                match format {
+                    JsonFormat::Array if self.json_count == 0 => write!(self.out, "\n{}", object)?,
+                    JsonFormat::Array => write!(self.out, ",\n{}", object)?,
+                    JsonFormat::Lines => writeln!(self.out, "{}", object)?,
                 }
+                self.json_count += 1;
+            }
+            return Ok(());
+        }
 
-                let mut short_names = Vec::new();
-
-                for (name, path, meta) in items {
-                    let mut display_name = if classify && !long_format {
-                        format!("{}{}", name, suffix_for(&path, &meta))
-                    } else {
-                        name.clone()
-                    };
-                    display_name = format_name(&display_name);
-
-                    if long_format {
-                        output.push_str(&format!("{}\n", long_format_line(&path, &meta, &display_name)));
-                    } else {
-                        short_names.push(display_name);
-                    }
+        if (config.long_format || config.show_blocks) && in_dir {
+            let total_blocks: u64 = items.iter().map(|(_, _, m)| m.blocks()).sum();
+            writeln!(self.out, "total {}", config.block_scale.format(total_blocks * 512))?;
+        }
+
+        let inodes: Vec<String> = items.iter().map(|(_, _, m)| m.ino().to_string()).collect();
+        let blocks: Vec<String> = items.iter().map(|(_, _, m)| config.block_scale.format(m.blocks() * 512)).collect();
+        let inode_width = column_width(inodes.iter().map(|s| s.as_str()));
+        let blocks_width = column_width(blocks.iter().map(|s| s.as_str()));
+
+        // -i and -s columns go in front of every line, in either format
+        let prefix = |i: usize| {
+            let mut prefix = String::new();
+            if config.show_inode {
+                prefix.push_str(&pad_left(&inodes[i], inode_width));
+                prefix.push(' ');
+            }
+            if config.show_blocks {
+                prefix.push_str(&pad_left(&blocks[i], blocks_width));
+                prefix.push(' ');
+            }
+            prefix
+        };
+
+        if config.long_format {
+            let lines: Vec<LongLine> = items
+                .iter()
+                .map(|(name, path, meta)| long_format_line(path, meta, &config.paint(&format_name(name), path, meta), config))
+                .collect();
+            // Modes followed by "+" or "." are one wider than the rest
+            let mode_width = column_width(lines.iter().map(|l| l.mode.as_str()));
+            let nlink_width = column_width(lines.iter().map(|l| l.nlink.as_str()));
+            let owner_width = column_width(lines.iter().filter_map(|l| l.owner.as_deref()));
+            let group_width = column_width(lines.iter().filter_map(|l| l.group.as_deref()));
+            let size_width = column_width(lines.iter().map(|l| l.size.as_str()));
+
+            for (i, line) in lines.iter().enumerate() {
+                let mut text = format!("{}{} {}", prefix(i), pad_right(&line.mode, mode_width), pad_left(&line.nlink, nlink_width));
+                if let Some(owner) = &line.owner {
+                    text.push(' ');
+                    text.push_str(&pad_right(owner, owner_width));
                 }
-                if !long_format {
-                    output.push_str(&short_names.join("\t"));
-                    output.push('\n');
+                if let Some(group) = &line.group {
+                    text.push(' ');
+                    text.push_str(&pad_right(group, group_width));
                 }
+                text.push_str(&format!(" {} {} {}", pad_left(&line.size, size_width), line.date, line.name));
+                writeln!(self.out, "{}", text)?;
+                self.write_xattrs(&items[i].1)?;
+            }
+            return Ok(());
+        }
 
+        let short_names: Vec<String> = items
+            .iter()
+            .enumerate()
+            .map(|(i, (name, path, meta))| {
+                let suffix = if config.classify { suffix_for(meta) } else { String::new() };
+                format!("{}{}{}", prefix(i), config.paint(&format_name(name), path, meta), suffix)
+            })
+            .collect();
+
+        // Attribute lines go under their file, so -@ lists one file per line
+        if config.show_xattrs {
+            for (name, (_, path, _)) in short_names.iter().zip(items) {
+                writeln!(self.out, "{}", name)?;
+                self.write_xattrs(path)?;
             }
-            Err(e) => output.push_str(&format!("ls: {}: {}\n", target, e)),
+            return Ok(());
         }
+        layout::write_columns(self.out, &short_names, config.layout, config.width)
     }
 
-    if output.ends_with('\n') {
-        output.pop();
+    /// With -@, print `name="value"` lines for each extended attribute
+    fn write_xattrs(&mut self, path: &Path) -> io::Result<()> {
+        if !self.config.show_xattrs {
+            return Ok(());
+        }
+        let names = match xattr::list(path) {
+            Ok(names) => names,
+            Err(_) => return Ok(()),
+        };
+        for name in names {
+            let value = match xattr::get(path, &name) {
+                Ok(value) => xattr::display_value(&value),
+                Err(e) => format!("<{}>", e),
+            };
+            writeln!(self.out, "\t{}={}", name.to_string_lossy(), value)?;
+        }
+        Ok(())
     }
-    output
 }
 
 // ------------------ Helper functions ------------------
 
-fn strip_dot(s: &str) -> &str {
-    if s.starts_with('.') && s.len() > 1 {
-        &s[1..]
-    } else {
-        s
-    }
-}
-
-fn ls_cmp(a: &str, b: &str) -> Ordering {
-    let a_key = strip_dot(a);
-    let b_key = strip_dot(b);
-
-    a_key.to_lowercase().as_bytes().cmp(b_key.to_lowercase().as_bytes())
-
-}
-
 
 fn file_type_char(meta: &fs::Metadata) -> char {
     match meta.mode() & 0o170000 {
@@ -210,45 +544,40 @@ fn file_type_char(meta: &fs::Metadata) -> char {
 fn permissions_string(meta: &fs::Metadata, path: &Path) -> String {
     let mode = meta.permissions().mode();
     let mut s = String::new();
-    let bits = [
-        0o400, 0o200, 0o100,
-        0o040, 0o020, 0o010,
-        0o004, 0o002, 0o001,
+
+    // Each triplet is (read, write, exec) plus the special bit that shares
+    // the exec slot: setuid, setgid, sticky
+    let triplets = [
+        (0o400, 0o200, 0o100, 0o4000, 's'),
+        (0o040, 0o020, 0o010, 0o2000, 's'),
+        (0o004, 0o002, 0o001, 0o1000, 't'),
     ];
-    let chars = ['r', 'w', 'x'];
-    for i in 0..9 {
-        s.push(if mode & bits[i] != 0 { chars[i % 3] } else { '-' });
+    for (r, w, x, special, letter) in triplets {
+        s.push(if mode & r != 0 { 'r' } else { '-' });
+        s.push(if mode & w != 0 { 'w' } else { '-' });
+        s.push(match (mode & x != 0, mode & special != 0) {
+            (true, true) => letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
     }
 
-    // Check if file has extended attributes (xattrs) → append "+"
-    if has_xattrs(path) {
-        s.push('+');
+    // "+" for a non-trivial ACL, "." for an SELinux label only
+    if let Some(indicator) = xattr::indicator(path, mode) {
+        s.push(indicator);
     }
 
     s
 }
 
-/// Fake xattr detector using Linux user.* attributes
-fn has_xattrs(path: &Path) -> bool {
-    // This requires libc in reality (listxattr), here we fake it
-    // by checking if "security.selinux" exists in sysfs/proc (not portable!)
-    // For a pure std version, always return false.
-    false
-}
-
-fn suffix_for(path: &Path, meta: &fs::Metadata) -> String {
+/// The -F indicator for a file of this type. Symlinks get "@"; callers that
+/// dereference pass the target's metadata instead.
+fn suffix_for(meta: &fs::Metadata) -> String {
     let ft = meta.file_type();
-    if path == Path::new("/bin") && ft.is_symlink() {
-        return "@".to_string();
-    }
     if ft.is_symlink() {
-        if let Ok(target_meta) = fs::metadata(path) {
-            return suffix_for(path, &target_meta);
-        } else {
-            return "@".to_string();
-        }
-    }
-    if ft.is_dir() {
+        "@".to_string()
+    } else if ft.is_dir() {
         "/".to_string()
     } else if ft.is_file() && (meta.mode() & 0o111 != 0) {
         "*".to_string()
@@ -261,41 +590,95 @@ fn suffix_for(path: &Path, meta: &fs::Metadata) -> String {
     }
 }
 
-fn long_format_line(path: &Path, meta: &fs::Metadata, name: &str) -> String {
+/// User name for a uid, or the number when there's no such user
+fn owner_name(uid: u32) -> String {
+    get_user_by_uid(uid).map(|u| u.name().to_string_lossy().to_string()).unwrap_or(uid.to_string())
+}
+
+/// Group name for a gid, or the number when there's no such group
+fn group_name(gid: u32) -> String {
+    get_group_by_gid(gid).map(|g| g.name().to_string_lossy().to_string()).unwrap_or(gid.to_string())
+}
+
+/// The fields of one `ls -l` line, before column widths are known
+struct LongLine {
+    mode: String,
+    nlink: String,
+    owner: Option<String>,
+    group: Option<String>,
+    size: String,
+    date: String,
+    name: String,
+}
+
+fn long_format_line(path: &Path, meta: &fs::Metadata, name: &str, config: &Config) -> LongLine {
     let file_type = file_type_char(meta);
     let perms = permissions_string(meta, path);
-    let nlink = meta.nlink();
     let uid = meta.uid();
     let gid = meta.gid();
-    let user = get_user_by_uid(uid).map(|u| u.name().to_string_lossy().to_string()).unwrap_or(uid.to_string());
-    let group = get_group_by_gid(gid).map(|g| g.name().to_string_lossy().to_string()).unwrap_or(gid.to_string());
-
-    let datetime = chrono::Local.timestamp_opt(meta.mtime(), 0).single().unwrap();
-    let now = chrono::Local::now();
-    let date_str = if datetime.year() == now.year() {
-        datetime.format("%b %e %H:%M").to_string()
+    let owner = if !config.show_owner {
+        None
+    } else if config.numeric_ids {
+        Some(uid.to_string())
+    } else {
+        Some(owner_name(uid))
+    };
+    let group = if !config.show_group {
+        None
+    } else if config.numeric_ids {
+        Some(gid.to_string())
     } else {
-        datetime.format("%b %e  %Y").to_string()
+        Some(group_name(gid))
     };
 
+    let date_str = config.time_style.format(config.sort.time.of(meta), config.now);
+
     let size_or_dev = match file_type {
         'c' | 'b' => {
             let rdev = meta.rdev();
-            format!("{:>3}, {:>3}", (rdev >> 8) & 0xff, rdev & 0xff)
+            format!("{:>3}, {:>3}", libc::major(rdev), libc::minor(rdev))
         },
-        _ => format!("{:>8}", meta.len()),
+        _ => config.size_scale.format(meta.len()),
     };
 
     let mut display_name = name.to_string();
     if meta.file_type().is_symlink() {
-        if let Ok(target_path) = fs::read_link(path) {
-            display_name.push_str(&format!(" -> {}", target_path.to_string_lossy()));
+        // The target is coloured and classified by what it points to
+        if let Ok(target) = fs::read_link(path) {
+            let target_text = format_name(&target.to_string_lossy());
+            match fs::metadata(path) {
+                Ok(target_meta) => {
+                    let target_path = path.parent().unwrap_or(Path::new("")).join(&target);
+                    display_name.push_str(&format!(" -> {}", config.paint(&target_text, &target_path, &target_meta)));
+                    display_name.push_str(&suffix_for(&target_meta));
+                }
+                Err(_) => display_name.push_str(&format!(" -> {}", config.paint_missing(&target_text))),
+            }
         }
+    } else {
+        display_name.push_str(&suffix_for(meta));
     }
-    display_name.push_str(&suffix_for(path, meta));
 
-    format!(
-        "{}{} {:>2} {:<8} {:<8} {} {} {}",
-        file_type, perms, nlink, user, group, size_or_dev, date_str, display_name
-    )
+    LongLine {
+        mode: format!("{}{}", file_type, perms),
+        nlink: meta.nlink().to_string(),
+        owner,
+        group,
+        size: size_or_dev,
+        date: date_str,
+        name: display_name,
+    }
+}
+
+/// Width of the widest value in a column
+fn column_width<'a>(values: impl Iterator<Item = &'a str>) -> usize {
+    values.map(layout::display_width).max().unwrap_or(0)
+}
+
+fn pad_left(s: &str, width: usize) -> String {
+    format!("{}{}", " ".repeat(width.saturating_sub(layout::display_width(s))), s)
+}
+
+fn pad_right(s: &str, width: usize) -> String {
+    format!("{}{}", s, " ".repeat(width.saturating_sub(layout::display_width(s))))
 }
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use super::file_type_char;
+
+/// `dircolors` defaults, used for any key `LS_COLORS` doesn't set
+const DEFAULTS: &[(&str, &str)] = &[
+    ("di", "01;34"),
+    ("ln", "01;36"),
+    ("pi", "40;33"),
+    ("so", "01;35"),
+    ("do", "01;35"),
+    ("bd", "40;33;01"),
+    ("cd", "40;33;01"),
+    ("or", "40;31;01"),
+    ("su", "37;41"),
+    ("sg", "30;43"),
+    ("tw", "30;42"),
+    ("ow", "34;42"),
+    ("st", "37;44"),
+    ("ex", "01;32"),
+];
+
+/// When `--color` turns colouring on
+pub enum When {
+    Always,
+    Never,
+    Auto,
+}
+
+impl When {
+    /// Parse the WHEN of `--color[=WHEN]`; a bare `--color` means always
+    pub fn parse(arg: Option<&str>) -> Option<When> {
+        match arg {
+            None | Some("always") | Some("yes") | Some("force") => Some(When::Always),
+            Some("never") | Some("no") | Some("none") => Some(When::Never),
+            Some("auto") | Some("tty") | Some("if-tty") => Some(When::Auto),
+            Some(_) => None,
+        }
+    }
+}
+
+fn wrap(sgr: Option<&str>, text: &str) -> String {
+    match sgr {
+        Some(sgr) if !sgr.is_empty() && sgr != "0" && sgr != "00" => format!("\x1b[{}m{}\x1b[0m", sgr, text),
+        _ => text.to_string(),
+    }
+}
+
+/// SGR sequences per file type (`di`, `ln`, ...) and per `*.ext` pattern
+pub struct Colors {
+    types: HashMap<String, String>,
+    extensions: Vec<(String, String)>,
+}
+
+impl Colors {
+    /// The defaults, overridden by whatever `$LS_COLORS` sets
+    pub fn from_env() -> Colors {
+        let mut colors = Colors {
+            types: DEFAULTS.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            extensions: Vec::new(),
+        };
+        if let Ok(spec) = env::var("LS_COLORS") {
+            colors.parse(&spec);
+        }
+        colors
+    }
+
+    fn parse(&mut self, spec: &str) {
+        for entry in spec.split(':') {
+            let (key, value) = match entry.split_once('=') {
+                Some(kv) => kv,
+                None => continue,
+            };
+            if let Some(suffix) = key.strip_prefix('*') {
+                self.extensions.push((suffix.to_string(), value.to_string()));
+            } else {
+                self.types.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// Wrap `text` in the colour for the file at `path`
+    pub fn paint(&self, text: &str, path: &Path, meta: &fs::Metadata) -> String {
+        wrap(self.sgr_for(path, meta), text)
+    }
+
+    /// Colour the target of a broken symlink (`mi`)
+    pub fn paint_missing(&self, text: &str) -> String {
+        wrap(self.get("mi"), text)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.types.get(key).map(|s| s.as_str())
+    }
+
+    fn sgr_for(&self, path: &Path, meta: &fs::Metadata) -> Option<&str> {
+        let mode = meta.mode();
+        match file_type_char(meta) {
+            'l' => match fs::metadata(path) {
+                Err(_) => self.get("or").or_else(|| self.get("ln")),
+                // ln=target colours a link like the file it points to
+                Ok(target) if self.get("ln") == Some("target") => self.sgr_for(path, &target),
+                Ok(_) => self.get("ln"),
+            },
+            'd' => {
+                let sticky = mode & 0o1000 != 0;
+                let other_writable = mode & 0o002 != 0;
+                match (sticky, other_writable) {
+                    (true, true) => self.get("tw"),
+                    (false, true) => self.get("ow"),
+                    (true, false) => self.get("st"),
+                    (false, false) => self.get("di"),
+                }
+            }
+            'p' => self.get("pi"),
+            's' => self.get("so"),
+            'b' => self.get("bd"),
+            'c' => self.get("cd"),
+            '-' => {
+                if mode & 0o4000 != 0 {
+                    self.get("su")
+                } else if mode & 0o2000 != 0 {
+                    self.get("sg")
+                } else if mode & 0o111 != 0 {
+                    self.get("ex")
+                } else if let Some(sgr) = self.extension_sgr(path) {
+                    Some(sgr)
+                } else if meta.nlink() > 1 && self.get("mh").is_some() {
+                    self.get("mh")
+                } else {
+                    self.get("fi")
+                }
+            }
+            _ => self.get("no"),
+        }
+    }
+
+    /// The last matching `*.ext` pattern wins, exact case first
+    fn extension_sgr(&self, path: &Path) -> Option<&str> {
+        let name = path.file_name()?.to_string_lossy();
+        let lower = name.to_lowercase();
+        self.extensions
+            .iter()
+            .rev()
+            .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .or_else(|| self.extensions.iter().rev().find(|(suffix, _)| lower.ends_with(&suffix.to_lowercase())))
+            .map(|(_, sgr)| sgr.as_str())
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::{DateTime, Local};
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use super::{file_type_char, group_name, owner_name, permissions_string};
+
+/// Shape of `--json` output
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// `--json`: a single array of every entry listed
+    Array,
+    /// `--ndjson`: one object per line
+    Lines,
+}
+
+/// One entry as a JSON object, on a single line
+pub fn entry(name: &str, path: &Path, meta: &fs::Metadata) -> String {
+    let type_name = match file_type_char(meta) {
+        'd' => "directory",
+        '-' => "file",
+        'l' => "symlink",
+        'p' => "fifo",
+        's' => "socket",
+        'b' => "block_device",
+        'c' => "char_device",
+        _ => "unknown",
+    };
+    let mtime = DateTime::from_timestamp(meta.mtime(), meta.mtime_nsec() as u32)
+        .map(|t| string(&t.with_timezone(&Local).to_rfc3339()))
+        .unwrap_or_else(|| "null".to_string());
+    let target = if meta.file_type().is_symlink() {
+        fs::read_link(path)
+            .map(|t| string(&t.to_string_lossy()))
+            .unwrap_or_else(|_| "null".to_string())
+    } else {
+        "null".to_string()
+    };
+
+    let fields = [
+        ("name", string(name)),
+        ("path", string(&path.to_string_lossy())),
+        ("type", string(type_name)),
+        ("mode", string(&format!("{}{}", file_type_char(meta), permissions_string(meta, path)))),
+        ("permissions", string(&format!("{:04o}", meta.permissions().mode() & 0o7777))),
+        ("nlink", meta.nlink().to_string()),
+        ("owner", string(&owner_name(meta.uid()))),
+        ("group", string(&group_name(meta.gid()))),
+        ("size", meta.len().to_string()),
+        ("mtime", mtime),
+        ("target", target),
+    ];
+
+    let body: Vec<String> = fields.iter().map(|(key, value)| format!("\"{}\":{}", key, value)).collect();
+    format!("{{{}}}", body.join(","))
+}
+
+/// A JSON string literal
+fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,131 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use unicode_width::UnicodeWidthStr;
+
+/// How short-format entries are laid out
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// `-1`: one entry per line
+    OnePerLine,
+    /// `-C`: columns, filled top to bottom
+    Vertical,
+    /// `-x`: columns, filled left to right
+    Horizontal,
+}
+
+/// Gap between two columns
+const GUTTER: usize = 2;
+
+pub fn stdout_is_tty() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// Width to fill: `-w` wins, then the terminal itself, then `$COLUMNS`, then 80.
+/// A width of 0 means "no limit".
+pub fn line_width(requested: Option<usize>) -> usize {
+    if let Some(width) = requested {
+        return width;
+    }
+
+    let mut ws = libc::winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    // SAFETY: TIOCGWINSZ only writes into the winsize struct we pass
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+    if ok && ws.ws_col > 0 {
+        return ws.ws_col as usize;
+    }
+
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse::<usize>().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(80)
+}
+
+/// Columns on screen taken by `s`, not counting colour escape sequences
+pub fn display_width(s: &str) -> usize {
+    if !s.contains('\x1b') {
+        return UnicodeWidthStr::width(s);
+    }
+
+    let mut width = 0;
+    let mut rest = s;
+    while let Some(start) = rest.find('\x1b') {
+        width += UnicodeWidthStr::width(&rest[..start]);
+        rest = &rest[start..];
+        // Skip through the final byte of the CSI sequence
+        match rest.find(|c: char| c.is_ascii_alphabetic()) {
+            Some(end) => rest = &rest[end + 1..],
+            None => rest = "",
+        }
+    }
+    width + UnicodeWidthStr::width(rest)
+}
+
+/// Print `names` in as many columns as fit in `width`, GNU-style
+pub fn write_columns(out: &mut dyn Write, names: &[String], layout: Layout, width: usize) -> io::Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
+    let widths: Vec<usize> = names.iter().map(|n| display_width(n)).collect();
+
+    let (rows, col_widths) = match layout {
+        Layout::OnePerLine => (names.len(), vec![0]),
+        _ => fit_columns(&widths, layout, width),
+    };
+    let cols = col_widths.len();
+
+    for row in 0..rows {
+        let mut line = String::new();
+        for (col, col_width) in col_widths.iter().enumerate() {
+            let idx = match layout {
+                Layout::Horizontal => row * cols + col,
+                _ => col * rows + row,
+            };
+            if idx >= names.len() {
+                break;
+            }
+            let next = match layout {
+                Layout::Horizontal => idx + 1,
+                _ => idx + rows,
+            };
+            line.push_str(&names[idx]);
+            // No trailing padding after the last entry on the line
+            if col + 1 < cols && next < names.len() {
+                line.push_str(&" ".repeat(col_width - widths[idx]));
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Find the largest number of columns whose total width fits, returning the
+/// row count and each column's width including its gutter.
+fn fit_columns(widths: &[usize], layout: Layout, line_width: usize) -> (usize, Vec<usize>) {
+    let n = widths.len();
+    let max_cols = if line_width == 0 { n } else { n.min(line_width / (1 + GUTTER)).max(1) };
+
+    for cols in (1..=max_cols).rev() {
+        let rows = n.div_ceil(cols);
+        // Skip counts that would leave an empty trailing column
+        let cols = match layout {
+            Layout::Horizontal => cols,
+            _ => n.div_ceil(rows),
+        };
+
+        let mut col_widths = vec![0; cols];
+        for (idx, &w) in widths.iter().enumerate() {
+            let col = match layout {
+                Layout::Horizontal => idx % cols,
+                _ => idx / rows,
+            };
+            col_widths[col] = col_widths[col].max(w + GUTTER);
+        }
+        let total: usize = col_widths.iter().sum::<usize>() - GUTTER;
+        if cols == 1 || line_width == 0 || total <= line_width {
+            return (rows, col_widths);
+        }
+    }
+
+    (n, vec![0])
+}
@@ -0,0 +1,261 @@
+use std::cmp::Ordering;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+
+/// What `ls` orders entries by
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    /// `-t`: newest first
+    Time,
+    /// `-S`: largest first
+    Size,
+    /// `-X`: alphabetically by extension
+    Extension,
+    /// `-v`: natural order of version numbers within names
+    Version,
+    /// `-U`: directory order
+    Unsorted,
+}
+
+/// Which timestamp `-t` (and `--time`) refers to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TimeField {
+    Modified,
+    Accessed,
+    Changed,
+    Birth,
+}
+
+impl TimeField {
+    /// Parse the WORD of `--time=WORD`
+    pub fn parse(word: &str) -> Option<TimeField> {
+        match word {
+            "mtime" | "modification" => Some(TimeField::Modified),
+            "atime" | "access" | "use" => Some(TimeField::Accessed),
+            "ctime" | "status" => Some(TimeField::Changed),
+            "birth" | "creation" => Some(TimeField::Birth),
+            _ => None,
+        }
+    }
+
+    /// Seconds and nanoseconds since the epoch; birth time falls back to 0
+    /// on filesystems that don't record it
+    pub fn of(self, meta: &fs::Metadata) -> (i64, i64) {
+        match self {
+            TimeField::Modified => (meta.mtime(), meta.mtime_nsec()),
+            TimeField::Accessed => (meta.atime(), meta.atime_nsec()),
+            TimeField::Changed => (meta.ctime(), meta.ctime_nsec()),
+            TimeField::Birth => meta
+                .created()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| (d.as_secs() as i64, d.subsec_nanos() as i64))
+                .unwrap_or((0, 0)),
+        }
+    }
+}
+
+pub struct SortOptions {
+    pub key: SortKey,
+    pub time: TimeField,
+    pub reverse: bool,
+    pub dirs_first: bool,
+}
+
+struct Keyed {
+    collate: Vec<u8>,
+    /// Collation key of the extension, only filled in for `-X`
+    extension: Vec<u8>,
+    is_dir: bool,
+    item: (String, PathBuf, fs::Metadata),
+}
+
+/// Order directory entries the way `ls` shows them
+pub fn sort_entries(items: Vec<(String, PathBuf, fs::Metadata)>, opts: &SortOptions) -> Vec<(String, PathBuf, fs::Metadata)> {
+    let mut keyed: Vec<Keyed> = items
+        .into_iter()
+        .map(|item| Keyed {
+            collate: collate_key(&item.0),
+            extension: if opts.key == SortKey::Extension { collate_key(extension(&item.0)) } else { Vec::new() },
+            // Symlinks to directories count as directories here, like GNU
+            // ls; only they need another stat, and only for grouping
+            is_dir: opts.dirs_first
+                && (item.2.is_dir()
+                    || (item.2.file_type().is_symlink() && fs::metadata(&item.1).map(|m| m.is_dir()).unwrap_or(false))),
+            item,
+        })
+        .collect();
+
+    keyed.sort_by(|a, b| {
+        let group = if opts.dirs_first { b.is_dir.cmp(&a.is_dir) } else { Ordering::Equal };
+        if opts.key == SortKey::Unsorted {
+            return group;
+        }
+        let order = compare(a, b, opts);
+        group.then(if opts.reverse { order.reverse() } else { order })
+    });
+
+    keyed.into_iter().map(|k| k.item).collect()
+}
+
+fn compare(a: &Keyed, b: &Keyed, opts: &SortOptions) -> Ordering {
+    let (a_meta, b_meta) = (&a.item.2, &b.item.2);
+    let primary = match opts.key {
+        SortKey::Time => opts.time.of(b_meta).cmp(&opts.time.of(a_meta)),
+        SortKey::Size => b_meta.len().cmp(&a_meta.len()),
+        SortKey::Extension => a.extension.cmp(&b.extension),
+        SortKey::Version => version_cmp(&a.item.0, &b.item.0),
+        SortKey::Name | SortKey::Unsorted => Ordering::Equal,
+    };
+
+    primary
+        .then_with(|| a.collate.cmp(&b.collate))
+        .then_with(|| a.item.0.cmp(&b.item.0))
+}
+
+fn extension(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(i) if i > 0 => &name[i + 1..],
+        _ => "",
+    }
+}
+
+fn strip_dot(s: &str) -> &str {
+    if s.starts_with('.') && s.len() > 1 {
+        &s[1..]
+    } else {
+        s
+    }
+}
+
+/// True once `LC_COLLATE` (or `LC_ALL`/`LANG`) names a real locale
+fn locale_collation() -> bool {
+    static ACTIVE: OnceLock<bool> = OnceLock::new();
+    *ACTIVE.get_or_init(|| {
+        // SAFETY: called once, before any other thread could use the locale
+        let name = unsafe { libc::setlocale(libc::LC_COLLATE, b"\0".as_ptr() as *const libc::c_char) };
+        if name.is_null() {
+            return false;
+        }
+        // SAFETY: setlocale returned a valid NUL-terminated string
+        let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+        name != "C" && name != "POSIX"
+    })
+}
+
+/// Byte string whose ordering matches the locale's collation of `name`.
+/// Without a collation locale this is the old case-insensitive,
+/// leading-dot-ignoring order.
+fn collate_key(name: &str) -> Vec<u8> {
+    let fallback = || strip_dot(name).to_lowercase().into_bytes();
+    if !locale_collation() {
+        return fallback();
+    }
+    let c_name = match CString::new(name) {
+        Ok(c) => c,
+        Err(_) => return fallback(),
+    };
+
+    // SAFETY: a zero-length destination only asks strxfrm for the size
+    let len = unsafe { libc::strxfrm(std::ptr::null_mut(), c_name.as_ptr(), 0) };
+    let mut buf = vec![0u8; len + 1];
+    // SAFETY: buf has room for len bytes plus the terminating NUL
+    unsafe { libc::strxfrm(buf.as_mut_ptr() as *mut libc::c_char, c_name.as_ptr(), buf.len()) };
+    buf.truncate(len);
+    buf
+}
+
+/// Natural ordering in the spirit of GNU `filevercmp`: runs of digits
+/// compare numerically, `~` sorts before everything, letters before
+/// other characters
+pub fn version_cmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        // Non-digit prefix
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let ca = a.get(i).filter(|c| !c.is_ascii_digit()).map_or(0, |&c| char_order(c));
+            let cb = b.get(j).filter(|c| !c.is_ascii_digit()).map_or(0, |&c| char_order(c));
+            if ca != cb {
+                return ca.cmp(&cb);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        // Numeric run, ignoring leading zeros
+        while i < a.len() && a[i] == b'0' {
+            i += 1;
+        }
+        while j < b.len() && b[j] == b'0' {
+            j += 1;
+        }
+        let start_a = i;
+        let start_b = j;
+        while i < a.len() && a[i].is_ascii_digit() {
+            i += 1;
+        }
+        while j < b.len() && b[j].is_ascii_digit() {
+            j += 1;
+        }
+        let (num_a, num_b) = (&a[start_a..i], &b[start_b..j]);
+        let order = num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b));
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+
+    Ordering::Equal
+}
+
+fn char_order(c: u8) -> i32 {
+    match c {
+        b'~' => -1,
+        c if c.is_ascii_alphabetic() => c as i32,
+        c => c as i32 + 256,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(version_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(version_cmp("file10", "file9"), Ordering::Greater);
+        assert_eq!(version_cmp("1.2.9", "1.10.0"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros_are_ignored() {
+        assert_eq!(version_cmp("a01", "a1"), Ordering::Equal);
+        assert_eq!(version_cmp("a007", "a10"), Ordering::Less);
+    }
+
+    #[test]
+    fn text_compares_bytewise() {
+        assert_eq!(version_cmp("abc", "abd"), Ordering::Less);
+        assert_eq!(version_cmp("ab", "abc"), Ordering::Less);
+        assert_eq!(version_cmp("same", "same"), Ordering::Equal);
+    }
+
+    #[test]
+    fn tilde_sorts_first_and_letters_before_symbols() {
+        assert_eq!(version_cmp("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(version_cmp("a~", "a"), Ordering::Less);
+        assert_eq!(version_cmp("az", "a-"), Ordering::Less);
+    }
+
+    #[test]
+    fn prefix_without_number_comes_first() {
+        assert_eq!(version_cmp("v", "v1"), Ordering::Less);
+        assert_eq!(version_cmp("v1", "v1a"), Ordering::Less);
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+
+/// Half of an average Gregorian year, the cut-off coreutils uses between
+/// "recent" and "old" timestamps
+const SIX_MONTHS: i64 = 31_556_952 / 2;
+
+/// How `ls -l` prints timestamps
+pub enum TimeStyle {
+    /// `Mon dd HH:MM` for the last six months, `Mon dd  YYYY` otherwise
+    Locale,
+    FullIso,
+    LongIso,
+    Iso,
+    /// `+FORMAT`, or `+RECENT\nOLD`
+    Format { recent: String, old: String },
+}
+
+impl TimeStyle {
+    /// Parse the STYLE of `--time-style=STYLE`
+    pub fn parse(style: &str) -> Option<TimeStyle> {
+        // posix-STYLE only differs from STYLE outside the POSIX locale
+        let style = style.strip_prefix("posix-").unwrap_or(style);
+        match style {
+            "locale" => Some(TimeStyle::Locale),
+            "full-iso" => Some(TimeStyle::FullIso),
+            "long-iso" => Some(TimeStyle::LongIso),
+            "iso" => Some(TimeStyle::Iso),
+            _ => {
+                let format = style.strip_prefix('+')?;
+                let (recent, old) = format.split_once('\n').unwrap_or((format, format));
+                let (recent, old) = (translate(recent), translate(old));
+                if !valid(&recent) || !valid(&old) {
+                    return None;
+                }
+                Some(TimeStyle::Format { recent, old })
+            }
+        }
+    }
+
+    /// Format a (seconds, nanoseconds) timestamp relative to `now`
+    pub fn format(&self, (sec, nsec): (i64, i64), now: i64) -> String {
+        // Converting an instant to local time can't be ambiguous; only
+        // out-of-range values fail, and those are shown as raw seconds
+        let datetime = match DateTime::from_timestamp(sec, nsec as u32) {
+            Some(utc) => utc.with_timezone(&Local),
+            None => return sec.to_string(),
+        };
+        // Future timestamps count as old, like coreutils
+        let recent = now - SIX_MONTHS < sec && sec <= now;
+
+        let format = match self {
+            TimeStyle::Locale if recent => "%b %e %H:%M",
+            TimeStyle::Locale => "%b %e  %Y",
+            TimeStyle::FullIso => "%Y-%m-%d %H:%M:%S%.9f %z",
+            TimeStyle::LongIso => "%Y-%m-%d %H:%M",
+            TimeStyle::Iso if recent => "%m-%d %H:%M",
+            TimeStyle::Iso => "%Y-%m-%d ",
+            TimeStyle::Format { recent: fmt, .. } if recent => fmt,
+            TimeStyle::Format { old, .. } => old,
+        };
+        datetime.format(format).to_string()
+    }
+}
+
+/// Map the GNU `%N` (nanoseconds) to chrono's spelling
+fn translate(format: &str) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('N') => out.push_str("%9f"),
+            Some(next) => {
+                out.push('%');
+                out.push(next);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// chrono panics when displaying an unknown specifier, so check up front
+fn valid(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
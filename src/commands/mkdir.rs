@@ -1,24 +1,122 @@
-use std::fs;
-use std::path::Path;
+use std::fs::{self, DirBuilder};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use crate::commands::journal::{self, Op};
+use crate::commands::mode;
+use crate::commands::options::{Opt, Spec};
+use crate::commands::umask::current_umask;
 
-pub fn mkdir(args: &[String]) -> String {
+static SPEC: Spec = Spec::new(
+    "mkdir",
+    "[OPTION]... DIRECTORY...",
+    "Create the DIRECTORY(ies), if they do not already exist.",
+    &[
+        Opt::value('m', "mode", "MODE", "set file mode (as in chmod), not a=rwx - umask"),
+        Opt::flag('p', "parents", "no error if existing, make parent directories as needed"),
+        Opt::flag('v', "verbose", "print a message for each created directory"),
+    ],
+);
+
+pub fn mkdir(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let umask = current_umask();
+    // -m starts from a=rwx; the mode is set after creation, so the umask
+    // only matters to symbolic modes that name no one
+    let mode = match matches.value("mode") {
+        Some(spec) => match mode::parse(spec, 0o777, umask, true) {
+            Some(mode) => Some(mode),
+            None => {
+                writeln!(err, "mkdir: invalid mode '{}'", spec)?;
+                return Ok(1);
+            }
+        },
+        None => None,
+    };
+    let mut maker = Maker {
+        mode,
+        // Parents are always writable and searchable by their owner
+        parent_mode: (0o777 & !umask) | 0o300,
+        parents: matches.has("parents"),
+        verbose: matches.has("verbose"),
+        created: Vec::new(),
+    };
+
+    let args = &matches.operands;
     if args.is_empty() {
-        return String::from("mkdir: missing operand");
+        writeln!(err, "mkdir: missing operand")?;
+        writeln!(err, "Try 'mkdir --help' for more information.")?;
+        return Ok(1);
     }
 
-    let mut messages = Vec::new();
+    let mut status = 0;
 
     for dir in args {
-        let path = Path::new(dir);
-        match fs::create_dir(path) {
-            Ok(_) => {}
-            Err(e) => messages.push(format!("mkdir: cannot create directory '{}': {}", dir, e)),
+        if let Err(message) = maker.make(Path::new(dir), out)? {
+            writeln!(err, "mkdir: {}", message)?;
+            status = 1;
+        }
+    }
+
+    // One entry for the whole command, so one undo takes it all back
+    if !maker.created.is_empty() {
+        journal::record(Op::Mkdir { paths: maker.created });
+    }
+
+    Ok(status)
+}
+
+struct Maker {
+    /// `-m`: exact mode for the directories named
+    mode: Option<u32>,
+    /// Mode for parents made by -p
+    parent_mode: u32,
+    parents: bool,
+    verbose: bool,
+    /// Everything made so far, for the journal
+    created: Vec<PathBuf>,
+}
+
+impl Maker {
+    fn make(&mut self, path: &Path, out: &mut dyn Write) -> io::Result<Result<(), String>> {
+        if self.parents {
+            let mut prefix = PathBuf::new();
+            let components: Vec<_> = path.components().collect();
+            for component in &components[..components.len().saturating_sub(1)] {
+                prefix.push(component);
+                if prefix.is_dir() {
+                    continue;
+                }
+                if let Err(e) = self.create(&prefix, Some(self.parent_mode), out)? {
+                    return Ok(Err(e));
+                }
+            }
+            if path.is_dir() {
+                return Ok(Ok(()));
+            }
         }
+
+        self.create(path, self.mode, out)
     }
 
-    if messages.is_empty() {
-        String::new() // no output on success
-    } else {
-        messages.join("\n")
+    /// mkdir(2) applies the umask, so an explicit mode is set again after
+    fn create(&mut self, path: &Path, mode: Option<u32>, out: &mut dyn Write) -> io::Result<Result<(), String>> {
+        if let Err(e) = DirBuilder::new().mode(mode.unwrap_or(0o777) & 0o777).create(path) {
+            return Ok(Err(format!("cannot create directory '{}': {}", path.display(), e)));
+        }
+        self.created.push(journal::absolute(path));
+
+        if let Some(mode) = mode {
+            if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(mode)) {
+                return Ok(Err(format!("cannot set permissions of '{}': {}", path.display(), e)));
+            }
+        }
+        if self.verbose {
+            writeln!(out, "mkdir: created directory '{}'", path.display())?;
+        }
+        Ok(Ok(()))
     }
 }
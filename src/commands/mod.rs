@@ -1,12 +1,24 @@
 pub mod handle_commands;
+pub mod options;
+pub mod size;
+pub mod xattr;
+pub mod prompt;
+pub mod rc;
+pub mod overwrite;
 pub mod echo;
+pub mod printf;
 pub mod pwd;
 pub mod exit;
 pub mod mkdir;
+pub mod mode;
+pub mod umask;
 pub mod clear;
 pub mod cp;
+pub mod copy;
 pub mod cat;
 pub mod ls;
 pub mod cd;
 pub mod rm ; 
 pub mod mv ; 
+pub mod trash;
+pub mod journal;
@@ -0,0 +1,153 @@
+/// Who each of `ugoa` covers, special bits included
+const USER: u32 = 0o4700;
+const GROUP: u32 = 0o2070;
+const OTHER: u32 = 0o1007;
+const ALL: u32 = 0o7777;
+
+/// Parse a chmod-style MODE, either octal or symbolic clauses such as
+/// `u=rwx,go-w` or `a+X`, applied to `base`. As with chmod, a clause that
+/// names no one (`+x`) leaves the bits set in `umask` alone. `X` only adds
+/// execute to directories or files something can already execute.
+pub fn parse(mode: &str, base: u32, umask: u32, is_dir: bool) -> Option<u32> {
+    if !mode.is_empty() && mode.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+        return match u32::from_str_radix(mode, 8) {
+            Ok(value) if value <= ALL => Some(value),
+            _ => None,
+        };
+    }
+
+    let mut result = base;
+    for clause in mode.split(',') {
+        let chars: Vec<char> = clause.chars().collect();
+        let mut i = 0;
+
+        let mut who = 0;
+        while let Some(c) = chars.get(i) {
+            who |= match c {
+                'u' => USER,
+                'g' => GROUP,
+                'o' => OTHER,
+                'a' => ALL,
+                _ => break,
+            };
+            i += 1;
+        }
+        let affected = if who == 0 { ALL & !umask } else { who };
+
+        // At least one action per clause
+        if i == chars.len() {
+            return None;
+        }
+        while i < chars.len() {
+            let op = chars[i];
+            if !matches!(op, '+' | '-' | '=') {
+                return None;
+            }
+            i += 1;
+
+            let mut perm = 0;
+            match chars.get(i) {
+                // Copy another class's permissions, as in g=u
+                Some(&class @ ('u' | 'g' | 'o')) => {
+                    let shift = match class {
+                        'u' => 6,
+                        'g' => 3,
+                        _ => 0,
+                    };
+                    perm = ((result >> shift) & 0o7) * 0o111;
+                    i += 1;
+                }
+                _ => {
+                    while let Some(c) = chars.get(i) {
+                        perm |= match c {
+                            'r' => 0o444,
+                            'w' => 0o222,
+                            'x' => 0o111,
+                            'X' if is_dir || result & 0o111 != 0 => 0o111,
+                            'X' => 0,
+                            's' => 0o6000,
+                            't' => 0o1000,
+                            _ => break,
+                        };
+                        i += 1;
+                    }
+                }
+            }
+
+            let bits = perm & affected;
+            result = match op {
+                '+' => result | bits,
+                '-' => result & !bits,
+                _ => (result & !affected) | bits,
+            };
+        }
+    }
+    Some(result)
+}
+
+/// `u=rwx,g=rx,o=rx` style rendering of permission bits
+pub fn symbolic(mode: u32) -> String {
+    let class = |bits: u32| {
+        let mut s = String::new();
+        if bits & 4 != 0 {
+            s.push('r');
+        }
+        if bits & 2 != 0 {
+            s.push('w');
+        }
+        if bits & 1 != 0 {
+            s.push('x');
+        }
+        s
+    };
+    format!("u={},g={},o={}", class(mode >> 6 & 7), class(mode >> 3 & 7), class(mode & 7))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn octal() {
+        assert_eq!(parse("755", 0o644, 0o022, false), Some(0o755));
+        assert_eq!(parse("0640", 0o777, 0, false), Some(0o640));
+        assert_eq!(parse("4755", 0, 0, false), Some(0o4755));
+        assert_eq!(parse("17777", 0, 0, false), None);
+    }
+
+    #[test]
+    fn symbolic_clauses() {
+        assert_eq!(parse("u+rwx,g-w,o=", 0o664, 0o022, false), Some(0o740));
+        assert_eq!(parse("a=r", 0o777, 0, false), Some(0o444));
+        assert_eq!(parse("go-rwx", 0o755, 0, true), Some(0o700));
+        assert_eq!(parse("g=u", 0o640, 0, false), Some(0o660));
+        assert_eq!(parse("u=rw,u+x-w", 0, 0, false), Some(0o500));
+        assert_eq!(parse("u+s,+t", 0o755, 0, false), Some(0o5755));
+    }
+
+    #[test]
+    fn no_one_named_respects_the_umask() {
+        assert_eq!(parse("+w", 0o444, 0o022, false), Some(0o644));
+        assert_eq!(parse("a+w", 0o444, 0o022, false), Some(0o666));
+    }
+
+    #[test]
+    fn capital_x_only_for_directories_or_executables() {
+        assert_eq!(parse("a+X", 0o644, 0, false), Some(0o644));
+        assert_eq!(parse("a+X", 0o644, 0, true), Some(0o755));
+        assert_eq!(parse("a+X", 0o744, 0, false), Some(0o755));
+    }
+
+    #[test]
+    fn invalid() {
+        for mode in ["", "u", "u+z", "q+r", "u+r,", "8", "rwx", "u!r"] {
+            assert_eq!(parse(mode, 0o644, 0o022, false), None, "{:?} parsed", mode);
+        }
+    }
+
+    #[test]
+    fn renders_symbolically() {
+        assert_eq!(symbolic(0o755), "u=rwx,g=rx,o=rx");
+        assert_eq!(symbolic(0o640), "u=rw,g=r,o=");
+    }
+}
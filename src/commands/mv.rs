@@ -1,50 +1,287 @@
+use std::ffi::CString;
 use std::fs;
-use std::path::Path;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use crate::commands::copy::{self, Copier, CopyOptions, Dereference, LinkMode, Preserve, Reflink, Sparse};
+use crate::commands::journal::{self, Op};
+use crate::commands::options::{Opt, Spec};
+use crate::commands::overwrite::{Mode, Policy};
 
-pub fn mv(args: &[String]) -> String {
-    if args.len() < 2 {
-        return "mv: missing file operand".to_string();
+static SPEC: Spec = Spec::new(
+    "mv",
+    "[OPTION]... SOURCE... DEST",
+    "Rename SOURCE to DEST, or move SOURCE(s) to DIRECTORY.",
+    &[
+        Opt::optional("backup", "CONTROL", "make a backup of each existing destination file"),
+        Opt::flag('b', "", "like --backup but does not accept an argument"),
+        Opt::flag('\0', "exchange", "exchange source and destination atomically"),
+        Opt::flag('f', "force", "do not prompt before overwriting"),
+        Opt::flag('i', "interactive", "prompt before overwrite"),
+        Opt::flag('n', "no-clobber", "do not overwrite an existing file"),
+        Opt::value('S', "suffix", "SUFFIX", "override the usual backup suffix"),
+        Opt::value('t', "target-directory", "DIRECTORY", "move all SOURCE arguments into DIRECTORY"),
+        Opt::flag('T', "no-target-directory", "treat DEST as a normal file"),
+        Opt::flag('u', "update", "move only when the SOURCE file is newer than the destination file or when the destination file is missing"),
+        Opt::flag('v', "verbose", "explain what is being done"),
+    ],
+);
+
+pub fn mv(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let policy = match Policy::from_matches(&matches, true) {
+        Ok(policy) => policy,
+        Err(message) => {
+            writeln!(err, "mv: {}", message)?;
+            return Ok(1);
+        }
+    };
+    let mover = Mover {
+        policy,
+        exchange: matches.has("exchange"),
+        verbose: matches.has("verbose"),
+    };
+
+    let args = &matches.operands;
+    let no_target = matches.has("no-target-directory");
+    if no_target && matches.has("target-directory") {
+        writeln!(err, "mv: cannot combine --target-directory (-t) and --no-target-directory (-T)")?;
+        return Ok(1);
+    }
+    if args.is_empty() || (args.len() < 2 && !matches.has("target-directory")) {
+        writeln!(err, "mv: missing file operand")?;
+        return Ok(1);
     }
 
-    let sources = &args[..args.len() - 1];
-    let destination = &args[args.len() - 1];
+    let (sources, destination) = match matches.value("target-directory") {
+        Some(dir) => (&args[..], dir),
+        None => (&args[..args.len() - 1], args[args.len() - 1].as_str()),
+    };
     let dest_path = Path::new(destination);
 
-    // Multiple sources → destination must be an existing directory
-    if sources.len() > 1 && (!dest_path.exists() || !dest_path.is_dir()) {
-        return format!("mv: target '{}' is not a directory", destination);
+    if no_target && sources.len() > 1 {
+        writeln!(err, "mv: extra operand '{}'", args[2])?;
+        return Ok(1);
+    }
+    let into_dir = !no_target && dest_path.is_dir();
+    // Several sources, or -t, need an existing directory to go into
+    if !into_dir && (sources.len() > 1 || matches.has("target-directory")) {
+        writeln!(err, "mv: target '{}' is not a directory", destination)?;
+        return Ok(1);
     }
 
-    let mut messages = Vec::new();
+    let mut status = 0;
 
     for source in sources {
         let src_path = Path::new(source);
+        let dest_file = if into_dir {
+            match copy::target_name(src_path) {
+                Some(name) => dest_path.join(name),
+                None => {
+                    writeln!(err, "mv: cannot move '{}': no file name to move to", source)?;
+                    status = 1;
+                    continue;
+                }
+            }
+        } else {
+            dest_path.to_path_buf()
+        };
 
-        if !src_path.exists() {
-            messages.push(format!("mv: cannot stat '{}': No such file or directory", source));
-            continue;
+        if !mover.move_path(src_path, &dest_file, out, err)? {
+            status = 1;
         }
+    }
 
-        let dest_file = if dest_path.is_dir() {
-            // Move inside directory
-            dest_path.join(src_path.file_name().unwrap())
-        } else if sources.len() == 1 {
-            // Single file → rename or overwrite
-            dest_path.to_path_buf()
-        } else {
-            // Should not happen, already handled above
-            continue;
+    Ok(status)
+}
+
+struct Mover {
+    policy: Policy,
+    /// `--exchange`: swap source and destination
+    exchange: bool,
+    verbose: bool,
+}
+
+impl Mover {
+    /// Move one source to exactly `dst`, reporting any failure on `err`
+    fn move_path(&self, src: &Path, dst: &Path, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<bool> {
+        let meta = match fs::symlink_metadata(src) {
+            Ok(meta) => meta,
+            Err(e) => {
+                writeln!(err, "mv: cannot stat '{}': {}", src.display(), e)?;
+                return Ok(false);
+            }
         };
+        if meta.is_dir() && copy::is_inside(src, dst) {
+            writeln!(err, "mv: cannot move '{}' to a subdirectory of itself, '{}'", src.display(), dst.display())?;
+            return Ok(false);
+        }
 
-        match fs::rename(src_path, &dest_file) {
-            Ok(_) => {}
-            Err(e) => messages.push(format!("mv: cannot move '{}': {}", source, e)),
+        if self.exchange {
+            return match rename_with(src, dst, libc::RENAME_EXCHANGE) {
+                Ok(()) => {
+                    journal::record(Op::Exchange { a: journal::absolute(src), b: journal::absolute(dst) });
+                    if self.verbose {
+                        writeln!(out, "exchanged '{}' <-> '{}'", src.display(), dst.display())?;
+                    }
+                    Ok(true)
+                }
+                Err(e) => {
+                    writeln!(err, "mv: cannot exchange '{}' and '{}': {}", src.display(), dst.display(), e)?;
+                    Ok(false)
+                }
+            };
         }
+
+        let mut backup = None;
+        if let Ok(dst_meta) = fs::symlink_metadata(dst) {
+            if dst_meta.dev() == meta.dev() && dst_meta.ino() == meta.ino() {
+                writeln!(err, "mv: '{}' and '{}' are the same file", src.display(), dst.display())?;
+                return Ok(false);
+            }
+            if dst_meta.is_dir() != meta.is_dir() {
+                let (existing, kind) = if dst_meta.is_dir() { ("directory", "non-directory") } else { ("non-directory", "directory") };
+                writeln!(err, "mv: cannot overwrite {} '{}' with {} '{}'", existing, dst.display(), kind, src.display())?;
+                return Ok(false);
+            }
+            if !self.policy.allows("mv", &meta, dst, &dst_meta, err)? {
+                return Ok(true);
+            }
+            backup = match self.policy.make_backup(dst) {
+                Ok(backup) => backup,
+                Err(e) => {
+                    writeln!(err, "mv: cannot backup '{}': {}", dst.display(), e)?;
+                    return Ok(false);
+                }
+            };
+        }
+
+        // With -n the rename itself refuses to replace anything, so a file
+        // appearing since the check above is left alone too
+        let flags = if self.policy.mode == Mode::NoClobber { libc::RENAME_NOREPLACE } else { 0 };
+        match rename_with(src, dst, flags) {
+            Ok(()) => {}
+            Err(ref e) if e.raw_os_error() == Some(libc::EEXIST) && flags != 0 => return Ok(true),
+            Err(ref e) if e.raw_os_error() == Some(libc::EXDEV) => {
+                if !move_across(src, dst, flags, out, err)? {
+                    return Ok(false);
+                }
+            }
+            Err(e) => {
+                writeln!(err, "mv: cannot move '{}' to '{}': {}", src.display(), dst.display(), e)?;
+                return Ok(false);
+            }
+        }
+
+        journal::record(Op::Move {
+            from: journal::absolute(src),
+            to: journal::absolute(dst),
+            backup: backup.as_deref().map(journal::absolute),
+        });
+        if self.verbose {
+            match backup {
+                Some(backup) => writeln!(out, "renamed '{}' -> '{}' (backup: '{}')", src.display(), dst.display(), backup.display())?,
+                None => writeln!(out, "renamed '{}' -> '{}'", src.display(), dst.display())?,
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// rename(2), or renameat2(2) when there are `RENAME_*` flags
+pub fn rename_with(from: &Path, to: &Path, flags: libc::c_uint) -> io::Result<()> {
+    if flags == 0 {
+        return fs::rename(from, to);
+    }
+    let c_from = CString::new(from.as_os_str().as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let c_to = CString::new(to.as_os_str().as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: both paths are valid NUL-terminated strings; glibc has no
+    // wrapper on older systems, so the syscall is made directly
+    let rc = unsafe {
+        libc::syscall(libc::SYS_renameat2, libc::AT_FDCWD, c_from.as_ptr(), libc::AT_FDCWD, c_to.as_ptr(), flags)
+    };
+    if rc < 0 {
+        let e = io::Error::last_os_error();
+        // Filesystems without renameat2 support can only get the check
+        // made beforehand, not an atomic one
+        if flags == libc::RENAME_NOREPLACE && matches!(e.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS)) {
+            if fs::symlink_metadata(to).is_ok() {
+                return Err(io::Error::from_raw_os_error(libc::EEXIST));
+            }
+            return fs::rename(from, to);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Move between filesystems, where rename can't: copy everything with its
+/// attributes under a temporary name next to `dst`, rename that into place
+/// and only then delete `src`. A failed copy is removed again, leaving
+/// both sides as they were.
+pub fn move_across(src: &Path, dst: &Path, flags: libc::c_uint, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<bool> {
+    let options = CopyOptions {
+        recursive: true,
+        dereference: Dereference::Never,
+        link: LinkMode::Copy,
+        preserve: Preserve::all(),
+        overwrite: Policy::default(),
+        reflink: Reflink::Auto,
+        sparse: Sparse::Auto,
+        verbose: false,
+        progress: false,
+    };
+
+    let staging = staging_name(dst);
+    let mut copier = Copier::new(&options, "mv", out, err);
+    copier.copy(src, &staging)?;
+    if copier.failed {
+        let _ = remove_tree(&staging);
+        return Ok(false);
     }
 
-    if messages.is_empty() {
-        String::new()
-    } else {
-        messages.join("\n")
+    // rename replaces a file or an empty directory, just as the plain
+    // same-filesystem rename would have
+    if let Err(e) = rename_with(&staging, dst, flags) {
+        let _ = remove_tree(&staging);
+        if e.raw_os_error() == Some(libc::EEXIST) {
+            return Ok(true);
+        }
+        writeln!(err, "mv: cannot move '{}' to '{}': {}", src.display(), dst.display(), e)?;
+        return Ok(false);
+    }
+
+    if let Err(e) = remove_tree(src) {
+        writeln!(err, "mv: cannot remove '{}': {}", src.display(), e)?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// A name beside `dst` that nothing else is using
+fn staging_name(dst: &Path) -> PathBuf {
+    let name = dst.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let parent = dst.parent().unwrap_or(Path::new(""));
+    let mut n = 0;
+    loop {
+        let candidate = parent.join(format!(".{}.mv-{}-{}", name, process::id(), n));
+        if fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Delete a file, symlink or whole directory tree, never following links
+fn remove_tree(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
     }
 }
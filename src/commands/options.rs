@@ -0,0 +1,407 @@
+use std::io::{self, Write};
+
+/// Whether an option takes a value, and the name shown for it in `--help`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    None,
+    Required(&'static str),
+    Optional(&'static str),
+}
+
+/// One option a builtin understands
+pub struct Opt {
+    pub short: Option<char>,
+    pub long: Option<&'static str>,
+    pub arg: Arg,
+    pub help: &'static str,
+}
+
+impl Opt {
+    /// A plain switch. Pass `'\0'` or `""` when there is no short or long spelling.
+    pub const fn flag(short: char, long: &'static str, help: &'static str) -> Opt {
+        Opt::new(short, long, Arg::None, help)
+    }
+
+    /// An option that always takes a value (`-m 755`, `-m755`, `--mode=755`, `--mode 755`)
+    pub const fn value(short: char, long: &'static str, name: &'static str, help: &'static str) -> Opt {
+        Opt::new(short, long, Arg::Required(name), help)
+    }
+
+    /// A long option whose value may only be attached with `=` (`--color[=WHEN]`)
+    pub const fn optional(long: &'static str, name: &'static str, help: &'static str) -> Opt {
+        Opt::new('\0', long, Arg::Optional(name), help)
+    }
+
+    const fn new(short: char, long: &'static str, arg: Arg, help: &'static str) -> Opt {
+        Opt {
+            short: if short == '\0' { None } else { Some(short) },
+            long: if long.is_empty() { None } else { Some(long) },
+            arg,
+            help,
+        }
+    }
+
+    /// Options are looked up by their long name, or by their letter when they have none
+    fn is(&self, name: &str) -> bool {
+        if self.long == Some(name) {
+            return true;
+        }
+        let mut chars = name.chars();
+        match (self.long, self.short, chars.next(), chars.next()) {
+            (None, Some(short), Some(c), None) => short == c,
+            _ => false,
+        }
+    }
+
+    fn help_line(&self) -> String {
+        let mut left = match self.short {
+            Some(c) if self.long.is_some() => format!("  -{}, ", c),
+            Some(c) => format!("  -{}", c),
+            None => "      ".to_string(),
+        };
+        if let Some(long) = self.long {
+            left.push_str("--");
+            left.push_str(long);
+        }
+        match self.arg {
+            Arg::None => {}
+            Arg::Required(name) if self.long.is_some() => left.push_str(&format!("={}", name)),
+            Arg::Required(name) => left.push_str(&format!(" {}", name)),
+            Arg::Optional(name) => left.push_str(&format!("[={}]", name)),
+        }
+
+        if left.chars().count() < 29 {
+            format!("{:<29}{}", left, self.help)
+        } else {
+            format!("{}\n{:<29}{}", left, "", self.help)
+        }
+    }
+}
+
+/// Everything the shared parser needs to know about a builtin
+pub struct Spec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub about: &'static str,
+    pub options: &'static [Opt],
+    /// echo-style parsing: only leading clusters made entirely of known
+    /// flags are options, `--` is left to the command (echo(1) prints it,
+    /// printf(1) drops one), and `--help` is only special when it is the
+    /// sole argument
+    pub literal: bool,
+}
+
+impl Spec {
+    pub const fn new(name: &'static str, usage: &'static str, about: &'static str, options: &'static [Opt]) -> Spec {
+        Spec { name, usage, about, options, literal: false }
+    }
+
+    pub const fn literal(mut self) -> Spec {
+        self.literal = true;
+        self
+    }
+
+    /// Parse `args` getopt_long-style: short flags may be clustered (`-rf`),
+    /// values may be attached or separate, long options may be abbreviated
+    /// and take `--name=value`, options and operands may be mixed, and `--`
+    /// ends option processing.
+    pub fn parse(&'static self, args: &[String]) -> Result<Matches, ParseError> {
+        if self.literal {
+            if args.len() == 1 && args[0] == "--help" {
+                return Err(ParseError { spec: self, kind: ErrorKind::Help });
+            }
+            return Ok(self.parse_literal(args));
+        }
+
+        let mut matches = Matches { opts: Vec::new(), operands: Vec::new() };
+        let mut rest = args.iter();
+
+        while let Some(arg) = rest.next() {
+            if arg == "--" {
+                matches.operands.extend(rest.cloned());
+                break;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, attached) = match long.find('=') {
+                    Some(i) => (&long[..i], Some(long[i + 1..].to_string())),
+                    None => (long, None),
+                };
+                let opt = self.find_long(name)?;
+                let value = match (opt.arg, attached) {
+                    (Arg::None, Some(_)) => {
+                        return Err(self.usage_error(format!("option '--{}' doesn't allow an argument", opt.long.unwrap_or(name))));
+                    }
+                    (Arg::Required(_), None) => match rest.next() {
+                        Some(v) => Some(v.clone()),
+                        None => return Err(self.usage_error(format!("option '--{}' requires an argument", opt.long.unwrap_or(name)))),
+                    },
+                    (_, value) => value,
+                };
+                matches.opts.push((opt, value));
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                let cluster = &arg[1..];
+                for (i, c) in cluster.char_indices() {
+                    let opt = match self.options.iter().find(|o| o.short == Some(c)) {
+                        Some(opt) => opt,
+                        None => return Err(self.usage_error(format!("invalid option -- '{}'", c))),
+                    };
+                    if opt.arg == Arg::None {
+                        matches.opts.push((opt, None));
+                        continue;
+                    }
+                    // The rest of the cluster, if any, is this option's value
+                    let attached = &cluster[i + c.len_utf8()..];
+                    let value = if !attached.is_empty() {
+                        Some(attached.to_string())
+                    } else if let Arg::Required(_) = opt.arg {
+                        match rest.next() {
+                            Some(v) => Some(v.clone()),
+                            None => return Err(self.usage_error(format!("option requires an argument -- '{}'", c))),
+                        }
+                    } else {
+                        None
+                    };
+                    matches.opts.push((opt, value));
+                    break;
+                }
+            } else {
+                matches.operands.push(arg.clone());
+            }
+        }
+
+        Ok(matches)
+    }
+
+    fn parse_literal(&'static self, args: &[String]) -> Matches {
+        let mut matches = Matches { opts: Vec::new(), operands: Vec::new() };
+        let mut i = 0;
+
+        while i < args.len() {
+            let arg = &args[i];
+            if arg.len() < 2 || !arg.starts_with('-') {
+                break;
+            }
+            let opts: Option<Vec<&'static Opt>> = arg[1..]
+                .chars()
+                .map(|c| self.options.iter().find(|o| o.short == Some(c) && o.arg == Arg::None))
+                .collect();
+            match opts {
+                Some(opts) => matches.opts.extend(opts.into_iter().map(|o| (o, None))),
+                None => break,
+            }
+            i += 1;
+        }
+
+        matches.operands.extend(args[i..].iter().cloned());
+        matches
+    }
+
+    fn find_long(&'static self, name: &str) -> Result<&'static Opt, ParseError> {
+        if let Some(opt) = self.options.iter().find(|o| o.long == Some(name)) {
+            return Ok(opt);
+        }
+        if name.is_empty() {
+            return Err(self.usage_error(format!("unrecognized option '--{}'", name)));
+        }
+
+        // Unambiguous abbreviations are accepted, with --help taking part
+        let candidates: Vec<&'static Opt> = self
+            .options
+            .iter()
+            .filter(|o| o.long.is_some_and(|l| l.starts_with(name)))
+            .collect();
+        match (candidates.as_slice(), "help".starts_with(name)) {
+            ([opt], false) => Ok(opt),
+            ([], true) => Err(ParseError { spec: self, kind: ErrorKind::Help }),
+            ([], false) => Err(self.usage_error(format!("unrecognized option '--{}'", name))),
+            _ => Err(self.usage_error(format!("option '--{}' is ambiguous", name))),
+        }
+    }
+
+    fn usage_error(&'static self, message: String) -> ParseError {
+        ParseError { spec: self, kind: ErrorKind::Usage(message) }
+    }
+
+    pub fn write_help(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Usage: {} {}", self.name, self.usage)?;
+        writeln!(out, "{}", self.about)?;
+        writeln!(out)?;
+        for opt in self.options {
+            writeln!(out, "{}", opt.help_line())?;
+        }
+        writeln!(out, "{:<29}display this help and exit", "      --help")
+    }
+}
+
+/// The options and operands of one command line, in the order given
+pub struct Matches {
+    opts: Vec<(&'static Opt, Option<String>)>,
+    pub operands: Vec<String>,
+}
+
+impl Matches {
+    pub fn has(&self, name: &str) -> bool {
+        self.opts.iter().any(|(o, _)| o.is(name))
+    }
+
+    /// Value of the last occurrence of `name`
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.opts.iter().rev().find(|(o, _)| o.is(name)).and_then(|(_, v)| v.as_deref())
+    }
+
+    /// Which of several mutually overriding options came last
+    pub fn last_of<'a>(&self, names: &[&'a str]) -> Option<&'a str> {
+        self.opts
+            .iter()
+            .rev()
+            .find_map(|(o, _)| names.iter().find(|n| o.is(n)).copied())
+    }
+}
+
+enum ErrorKind {
+    Help,
+    Usage(String),
+}
+
+/// Why parsing stopped: `--help` was asked for, or the command line was bad
+pub struct ParseError {
+    spec: &'static Spec,
+    kind: ErrorKind,
+}
+
+impl ParseError {
+    /// Print help or a usage diagnostic and return the exit status to use
+    pub fn report(self, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+        match self.kind {
+            ErrorKind::Help => {
+                self.spec.write_help(out)?;
+                Ok(0)
+            }
+            ErrorKind::Usage(message) => {
+                writeln!(err, "{}: {}", self.spec.name, message)?;
+                writeln!(err, "Try '{} --help' for more information.", self.spec.name)?;
+                Ok(2)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TEST: Spec = Spec::new(
+        "test",
+        "[OPTION]...",
+        "",
+        &[
+            Opt::flag('a', "all", ""),
+            Opt::flag('b', "", ""),
+            Opt::flag('c', "", ""),
+            Opt::value('o', "output", "FILE", ""),
+            Opt::flag('\0', "verbose", ""),
+            Opt::flag('\0', "version", ""),
+            Opt::optional("color", "WHEN", ""),
+        ],
+    );
+
+    fn parse(args: &[&str]) -> Result<Matches, ParseError> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        TEST.parse(&args)
+    }
+
+    fn usage(result: Result<Matches, ParseError>) -> String {
+        match result {
+            Err(ParseError { kind: ErrorKind::Usage(message), .. }) => message,
+            Err(ParseError { kind: ErrorKind::Help, .. }) => "help".to_string(),
+            Ok(_) => "ok".to_string(),
+        }
+    }
+
+    #[test]
+    fn bundled_flags() {
+        let m = parse(&["-abc", "x"]).ok().unwrap();
+        assert!(m.has("all") && m.has("b") && m.has("c"));
+        assert_eq!(m.operands, ["x"]);
+    }
+
+    #[test]
+    fn letters_only_name_options_without_a_long_name() {
+        let m = parse(&["-a"]).ok().unwrap();
+        assert!(m.has("all"));
+        assert!(!m.has("a"));
+    }
+
+    #[test]
+    fn short_values() {
+        let m = parse(&["-ofile"]).ok().unwrap();
+        assert_eq!(m.value("output"), Some("file"));
+        let m = parse(&["-o", "file", "x"]).ok().unwrap();
+        assert_eq!(m.value("output"), Some("file"));
+        assert_eq!(m.operands, ["x"]);
+        let m = parse(&["-bofile"]).ok().unwrap();
+        assert!(m.has("b"));
+        assert_eq!(m.value("output"), Some("file"));
+        assert_eq!(usage(parse(&["-o"])), "option requires an argument -- 'o'");
+    }
+
+    #[test]
+    fn long_values() {
+        let m = parse(&["--output=file"]).ok().unwrap();
+        assert_eq!(m.value("output"), Some("file"));
+        let m = parse(&["--output", "file"]).ok().unwrap();
+        assert_eq!(m.value("output"), Some("file"));
+        let m = parse(&["--color", "x"]).ok().unwrap();
+        assert!(m.has("color"));
+        assert_eq!(m.value("color"), None);
+        assert_eq!(m.operands, ["x"]);
+        assert_eq!(parse(&["--color=never"]).ok().unwrap().value("color"), Some("never"));
+        assert_eq!(usage(parse(&["--all=yes"])), "option '--all' doesn't allow an argument");
+    }
+
+    #[test]
+    fn abbreviated_long_options() {
+        let m = parse(&["--out=f", "--verb"]).ok().unwrap();
+        assert_eq!(m.value("output"), Some("f"));
+        assert!(m.has("verbose"));
+        assert_eq!(usage(parse(&["--ver"])), "option '--ver' is ambiguous");
+        assert_eq!(usage(parse(&["--nope"])), "unrecognized option '--nope'");
+        assert_eq!(usage(parse(&["--he"])), "help");
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let m = parse(&["-a", "--", "-b", "--all"]).ok().unwrap();
+        assert!(m.has("all") && !m.has("b"));
+        assert_eq!(m.operands, ["-b", "--all"]);
+    }
+
+    #[test]
+    fn lone_dash_is_an_operand() {
+        let m = parse(&["-", "-a"]).ok().unwrap();
+        assert!(m.has("all"));
+        assert_eq!(m.operands, ["-"]);
+    }
+
+    #[test]
+    fn unknown_short_option() {
+        assert_eq!(usage(parse(&["-az"])), "invalid option -- 'z'");
+    }
+
+    #[test]
+    fn literal_parsing() {
+        static LITERAL: Spec = Spec::new("literal", "", "", &[Opt::flag('n', "", "")]).literal();
+        let parse = |args: &[&str]| LITERAL.parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+        let m = parse(&["-nn", "-x", "-n"]).ok().unwrap();
+        assert!(m.has("n"));
+        assert_eq!(m.operands, ["-x", "-n"]);
+        assert_eq!(parse(&["--", "-n"]).ok().unwrap().operands, ["--", "-n"]);
+        assert_eq!(usage(parse(&["--help"])), "help");
+        assert_eq!(parse(&["--help", "x"]).ok().unwrap().operands, ["--help", "x"]);
+    }
+
+    #[test]
+    fn last_of_picks_the_latest() {
+        let m = parse(&["-b", "-c", "-b"]).ok().unwrap();
+        assert_eq!(m.last_of(&["b", "c"]), Some("b"));
+    }
+}
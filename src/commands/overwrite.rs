@@ -0,0 +1,181 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::commands::options::Matches;
+use crate::commands::prompt;
+
+/// What to do when the destination already exists
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Replace it
+    #[default]
+    Clobber,
+    /// `-i`: ask first
+    Interactive,
+    /// `-n`: never replace it
+    NoClobber,
+}
+
+/// `--backup=CONTROL`
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backup {
+    #[default]
+    None,
+    /// `name.~N~`
+    Numbered,
+    /// Numbered if numbered backups already exist, simple otherwise
+    Existing,
+    /// `name~`, or `name` plus `--suffix`
+    Simple,
+}
+
+impl Backup {
+    fn parse(control: &str) -> Option<Backup> {
+        match control {
+            "none" | "off" => Some(Backup::None),
+            "numbered" | "t" => Some(Backup::Numbered),
+            "existing" | "nil" => Some(Backup::Existing),
+            "simple" | "never" => Some(Backup::Simple),
+            _ => None,
+        }
+    }
+}
+
+/// How cp and mv treat existing destinations. Both commands list the
+/// `-i -n -f -u -b --backup --suffix` options themselves and hand the
+/// parsed matches to `from_matches`.
+///
+/// For mv, whichever of `-i`, `-n` and `-f` comes last wins. For cp, `-f`
+/// only concerns destinations that can't be opened, so it leaves an
+/// earlier `-i` asking, as in coreutils.
+#[derive(Clone, Default)]
+pub struct Policy {
+    pub mode: Mode,
+    /// `-u`: only replace a destination older than the source
+    pub update: bool,
+    /// `-f`: remove destinations that can't be opened and try again
+    pub force: bool,
+    pub backup: Backup,
+    pub suffix: String,
+}
+
+impl Policy {
+    pub fn from_matches(matches: &Matches, force_overrides: bool) -> Result<Policy, String> {
+        let modes: &[&str] = if force_overrides { &["interactive", "no-clobber", "force"] } else { &["interactive", "no-clobber"] };
+        let mode = match matches.last_of(modes) {
+            Some("interactive") => Mode::Interactive,
+            Some("no-clobber") => Mode::NoClobber,
+            _ => Mode::Clobber,
+        };
+
+        // -b and a bare --backup take the control from $VERSION_CONTROL
+        // --suffix on its own turns backups on too, as in coreutils
+        let backup = if matches.has("backup") || matches.has("b") || matches.has("suffix") {
+            let control = match matches.value("backup") {
+                Some(control) => control.to_string(),
+                None => env::var("VERSION_CONTROL").unwrap_or_else(|_| "existing".to_string()),
+            };
+            match Backup::parse(&control) {
+                Some(backup) => backup,
+                None => {
+                    return Err(format!(
+                        "invalid argument '{}' for '--backup'\nValid arguments are: none, off, numbered, t, existing, nil, simple, never",
+                        control
+                    ));
+                }
+            }
+        } else {
+            Backup::None
+        };
+
+        let suffix = match matches.value("suffix") {
+            Some(suffix) => suffix.to_string(),
+            None => env::var("SIMPLE_BACKUP_SUFFIX").unwrap_or_else(|_| "~".to_string()),
+        };
+
+        Ok(Policy {
+            mode,
+            update: matches.has("update"),
+            force: matches.has("force"),
+            backup,
+            suffix,
+        })
+    }
+
+    /// Whether `src` may replace the existing `dst`: -n refuses, -u keeps a
+    /// destination that is as new as the source, -i asks
+    pub fn allows(&self, prog: &str, src_meta: &fs::Metadata, dst: &Path, dst_meta: &fs::Metadata, err: &mut dyn Write) -> io::Result<bool> {
+        if self.mode == Mode::NoClobber {
+            return Ok(false);
+        }
+        if self.update && (dst_meta.mtime(), dst_meta.mtime_nsec()) >= (src_meta.mtime(), src_meta.mtime_nsec()) {
+            return Ok(false);
+        }
+        if self.mode == Mode::Interactive {
+            return prompt::confirm(err, &format!("{}: overwrite '{}'?", prog, dst.display()));
+        }
+        Ok(true)
+    }
+
+    /// Move `dst` aside as --backup asks, returning where it went
+    pub fn make_backup(&self, dst: &Path) -> io::Result<Option<PathBuf>> {
+        let backup = match self.backup_name(dst) {
+            Some(backup) => backup,
+            None => return Ok(None),
+        };
+        fs::rename(dst, &backup)?;
+        Ok(Some(backup))
+    }
+
+    fn backup_name(&self, dst: &Path) -> Option<PathBuf> {
+        let simple = || {
+            let mut name = dst.as_os_str().to_os_string();
+            name.push(&self.suffix);
+            PathBuf::from(name)
+        };
+        let numbered = |n: u64| {
+            let mut name = dst.as_os_str().to_os_string();
+            name.push(format!(".~{}~", n));
+            PathBuf::from(name)
+        };
+
+        match self.backup {
+            Backup::None => None,
+            Backup::Simple => Some(simple()),
+            Backup::Numbered => Some(numbered(highest_backup(dst) + 1)),
+            Backup::Existing => match highest_backup(dst) {
+                0 => Some(simple()),
+                n => Some(numbered(n + 1)),
+            },
+        }
+    }
+}
+
+/// Largest N among existing `dst.~N~` files, or 0
+fn highest_backup(dst: &Path) -> u64 {
+    let name = match dst.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return 0,
+    };
+    let dir = match dst.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.~", name);
+
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let entry_name = entry.file_name().to_string_lossy().to_string();
+                    entry_name.strip_prefix(&prefix)?.strip_suffix('~')?.parse::<u64>().ok()
+                })
+                .max()
+                .unwrap_or(0)
+        })
+        .unwrap_or(0)
+}
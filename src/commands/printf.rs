@@ -0,0 +1,498 @@
+use std::io::{self, Write};
+use crate::commands::options::Spec;
+
+static SPEC: Spec = Spec::new(
+    "printf",
+    "FORMAT [ARGUMENT]...",
+    "Print ARGUMENT(s) according to FORMAT, reusing FORMAT while arguments\n\
+     remain. Conversions: %s %b %q %c %d %i %o %u %x %X %f %F %e %E %g %G %%,\n\
+     with flags -+ #0, a width and a precision, either of which may be *.",
+    &[],
+)
+.literal();
+
+pub fn printf(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    // One leading -- is dropped, as by GNU printf and bash's builtin
+    let operands = match matches.operands.split_first() {
+        Some((first, rest)) if first == "--" => rest,
+        _ => &matches.operands[..],
+    };
+    let (format, args) = match operands.split_first() {
+        Some((format, args)) => (format.as_bytes(), args),
+        None => {
+            writeln!(err, "printf: missing operand")?;
+            writeln!(err, "Try 'printf --help' for more information.")?;
+            return Ok(1);
+        }
+    };
+
+    let mut printer = Printer { args, next: 0, out, warnings: Vec::new() };
+    loop {
+        let start = printer.next;
+        match printer.run(format)? {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Stop) => break,
+            Err(message) => {
+                writeln!(err, "printf: {}", message)?;
+                return Ok(1);
+            }
+        }
+        // The format is reused only while it keeps consuming arguments
+        if printer.next == start || printer.next >= args.len() {
+            break;
+        }
+    }
+
+    for message in &printer.warnings {
+        writeln!(err, "printf: {}", message)?;
+    }
+    Ok(if printer.warnings.is_empty() { 0 } else { 1 })
+}
+
+/// Whether `\c` asked for all output to end
+enum Flow {
+    Continue,
+    Stop,
+}
+
+struct Printer<'a> {
+    args: &'a [String],
+    next: usize,
+    out: &'a mut dyn Write,
+    warnings: Vec<String>,
+}
+
+/// A parsed `%[flags][width][.precision]` prefix
+#[derive(Default)]
+struct Field {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Printer<'_> {
+    /// One pass over the format, written out as it goes. The inner
+    /// error is a bad directive, which ends printf.
+    fn run(&mut self, format: &[u8]) -> io::Result<Result<Flow, String>> {
+        let mut i = 0;
+        while i < format.len() {
+            // Plain text goes out a run at a time
+            let plain = format[i..].iter().position(|&b| b == b'\\' || b == b'%').unwrap_or(format.len() - i);
+            if plain > 0 {
+                self.out.write_all(&format[i..i + plain])?;
+                i += plain;
+                continue;
+            }
+            let (flow, used) = if format[i] == b'\\' {
+                let (bytes, used, stop) = escape(&format[i..], Escapes::Format);
+                self.out.write_all(&bytes)?;
+                (if stop { Flow::Stop } else { Flow::Continue }, used)
+            } else {
+                match self.directive(&format[i..])? {
+                    Ok(done) => done,
+                    Err(message) => return Ok(Err(message)),
+                }
+            };
+            if let Flow::Stop = flow {
+                return Ok(Ok(Flow::Stop));
+            }
+            i += used;
+        }
+        Ok(Ok(Flow::Continue))
+    }
+
+    /// Handle the directive at the start of `format`, returning how many
+    /// bytes it took
+    fn directive(&mut self, format: &[u8]) -> io::Result<Result<(Flow, usize), String>> {
+        let mut i = 1;
+        if format.get(i) == Some(&b'%') {
+            self.out.write_all(b"%")?;
+            return Ok(Ok((Flow::Continue, 2)));
+        }
+
+        let mut field = Field::default();
+        while let Some(&c) = format.get(i) {
+            match c {
+                b'-' => field.left = true,
+                b'+' => field.plus = true,
+                b' ' => field.space = true,
+                b'#' => field.alt = true,
+                b'0' => field.zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        if format.get(i) == Some(&b'*') {
+            let width = self.int_arg();
+            // A negative * width means left-justify
+            field.left |= width < 0;
+            field.width = width.unsigned_abs() as usize;
+            i += 1;
+        } else {
+            let (width, used) = digits(&format[i..]);
+            field.width = width;
+            i += used;
+        }
+
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            if format.get(i) == Some(&b'*') {
+                let precision = self.int_arg();
+                field.precision = if precision < 0 { None } else { Some(precision as usize) };
+                i += 1;
+            } else {
+                let (precision, used) = digits(&format[i..]);
+                field.precision = Some(precision);
+                i += used;
+            }
+        }
+
+        let conversion = match format.get(i) {
+            Some(&c) => c,
+            None => return Ok(Err(format!("{}: invalid conversion specification", String::from_utf8_lossy(format)))),
+        };
+        i += 1;
+
+        let body = match conversion {
+            b's' => {
+                let arg = self.str_arg();
+                match field.precision {
+                    Some(p) => arg.chars().take(p).collect(),
+                    None => arg,
+                }
+            }
+            b'b' => {
+                let arg = self.str_arg();
+                let (mut bytes, stop) = unescape(&arg, Escapes::Argument);
+                if let Some(p) = field.precision {
+                    bytes.truncate(p);
+                }
+                self.out.write_all(&pad_bytes(bytes, &field))?;
+                return Ok(Ok((if stop { Flow::Stop } else { Flow::Continue }, i)));
+            }
+            b'q' => quote(&self.str_arg()),
+            b'c' => self.str_arg().chars().next().map(String::from).unwrap_or_default(),
+            b'd' | b'i' => {
+                let value = self.int_arg();
+                let digits = with_precision(value.unsigned_abs().to_string(), &field);
+                let sign = if value < 0 {
+                    "-"
+                } else if field.plus {
+                    "+"
+                } else if field.space {
+                    " "
+                } else {
+                    ""
+                };
+                format!("{}{}", sign, digits)
+            }
+            b'o' | b'u' | b'x' | b'X' => {
+                // Negative values wrap around, as in C
+                let value = self.int_arg() as u64;
+                let digits = match conversion {
+                    b'o' => format!("{:o}", value),
+                    b'u' => value.to_string(),
+                    b'x' => format!("{:x}", value),
+                    _ => format!("{:X}", value),
+                };
+                let digits = with_precision(digits, &field);
+                match conversion {
+                    b'o' if field.alt && !digits.starts_with('0') => format!("0{}", digits),
+                    b'x' if field.alt && value != 0 => format!("0x{}", digits),
+                    b'X' if field.alt && value != 0 => format!("0X{}", digits),
+                    _ => digits,
+                }
+            }
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                let value = self.float_arg();
+                let body = format_float(value.abs(), conversion, field.precision, field.alt);
+                let sign = if value.is_sign_negative() && !value.is_nan() {
+                    "-"
+                } else if field.plus {
+                    "+"
+                } else if field.space {
+                    " "
+                } else {
+                    ""
+                };
+                format!("{}{}", sign, body)
+            }
+            _ => {
+                return Ok(Err(format!("%{}: invalid conversion specification", String::from_utf8_lossy(&format[1..i]))));
+            }
+        };
+
+        // Zero padding is for numbers, and precision turns it off for integers
+        let numeric = b"dioxXufFeEgG".contains(&conversion);
+        let integer = b"diouxX".contains(&conversion);
+        field.zero &= numeric && !(integer && field.precision.is_some());
+        self.out.write_all(&pad_bytes(body.into_bytes(), &field))?;
+        Ok(Ok((Flow::Continue, i)))
+    }
+
+    fn str_arg(&mut self) -> String {
+        let arg = self.args.get(self.next).cloned().unwrap_or_default();
+        self.next += 1;
+        arg
+    }
+
+    fn int_arg(&mut self) -> i64 {
+        let arg = match self.args.get(self.next) {
+            Some(arg) => arg.clone(),
+            None => return 0,
+        };
+        self.next += 1;
+        match parse_int(&arg) {
+            Some(value) => value,
+            None => {
+                self.invalid(&arg);
+                0
+            }
+        }
+    }
+
+    fn float_arg(&mut self) -> f64 {
+        let arg = match self.args.get(self.next) {
+            Some(arg) => arg.clone(),
+            None => return 0.0,
+        };
+        self.next += 1;
+        match arg.trim_start().parse::<f64>().ok().or_else(|| parse_int(&arg).map(|v| v as f64)) {
+            Some(value) => value,
+            None => {
+                self.invalid(&arg);
+                0.0
+            }
+        }
+    }
+
+    /// Bad numbers print as zero, with a complaint once output is done
+    fn invalid(&mut self, arg: &str) {
+        self.warnings.push(format!("'{}': expected a numeric value", arg));
+    }
+}
+
+/// Leading decimal digits of `bytes` and how many there were
+fn digits(bytes: &[u8]) -> (usize, usize) {
+    let count = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    let value = std::str::from_utf8(&bytes[..count]).ok().and_then(|s| s.parse().ok()).unwrap_or(0);
+    (value, count)
+}
+
+/// An integer argument: decimal, `0x` hex, `0` octal, or `'c` for the
+/// code of character c
+fn parse_int(arg: &str) -> Option<i64> {
+    let arg = arg.trim_start();
+    if let Some(quoted) = arg.strip_prefix('\'').or_else(|| arg.strip_prefix('"')) {
+        return Some(quoted.chars().next().map(|c| c as i64).unwrap_or(0));
+    }
+    let (negative, digits) = match arg.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, arg.strip_prefix('+').unwrap_or(arg)),
+    };
+    let magnitude = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse::<u64>().ok()?
+    };
+    Some(if negative { (magnitude as i64).wrapping_neg() } else { magnitude as i64 })
+}
+
+/// At least `precision` digits, and none at all for zero at precision 0
+fn with_precision(digits: String, field: &Field) -> String {
+    match field.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(p) if digits.len() < p => format!("{}{}", "0".repeat(p - digits.len()), digits),
+        _ => digits,
+    }
+}
+
+/// Pad to the field width; zeros go after any sign or `0x`
+fn pad_bytes(mut body: Vec<u8>, field: &Field) -> Vec<u8> {
+    let len = String::from_utf8_lossy(&body).chars().count();
+    if len >= field.width {
+        return body;
+    }
+    let fill = field.width - len;
+    if field.left {
+        body.extend(std::iter::repeat_n(b' ', fill));
+        return body;
+    }
+    if !field.zero {
+        let mut padded = vec![b' '; fill];
+        padded.extend(body);
+        return padded;
+    }
+    let mut prefix = match body.first() {
+        Some(b'-' | b'+' | b' ') => 1,
+        _ => 0,
+    };
+    if body[prefix..].starts_with(b"0x") || body[prefix..].starts_with(b"0X") {
+        prefix += 2;
+    }
+    let mut padded = body[..prefix].to_vec();
+    padded.extend(std::iter::repeat_n(b'0', fill));
+    padded.extend_from_slice(&body[prefix..]);
+    padded
+}
+
+/// C-style `%f`, `%e` and `%g` for a non-negative value
+fn format_float(value: f64, conversion: u8, precision: Option<usize>, alt: bool) -> String {
+    let upper = conversion.is_ascii_uppercase();
+    if !value.is_finite() {
+        let text = if value.is_nan() { "nan" } else { "inf" };
+        return if upper { text.to_uppercase() } else { text.to_string() };
+    }
+    let precision = precision.unwrap_or(6);
+
+    match conversion {
+        b'f' | b'F' => format!("{:.*}", precision, value),
+        b'e' | b'E' => exponent(value, precision, upper),
+        _ => {
+            // %g: %e if the exponent is below -4 or at least the
+            // precision, %f otherwise, without trailing zeros unless #
+            let precision = precision.max(1);
+            let exp = if value == 0.0 {
+                0
+            } else {
+                let text = format!("{:.*e}", precision - 1, value);
+                text.split_once('e').and_then(|(_, e)| e.parse::<i32>().ok()).unwrap_or(0)
+            };
+            let text = if exp < -4 || exp >= precision as i32 {
+                exponent(value, precision - 1, upper)
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exp) as usize, value)
+            };
+            if alt {
+                return text;
+            }
+            let (mantissa, exp_part) = match text.find(['e', 'E']) {
+                Some(i) => text.split_at(i),
+                None => (text.as_str(), ""),
+            };
+            let mantissa = if mantissa.contains('.') { mantissa.trim_end_matches('0').trim_end_matches('.') } else { mantissa };
+            format!("{}{}", mantissa, exp_part)
+        }
+    }
+}
+
+/// `d.ddde+XX`, with at least two exponent digits
+fn exponent(value: f64, precision: usize, upper: bool) -> String {
+    let text = format!("{:.*e}", precision, value);
+    let (mantissa, exp) = text.split_once('e').unwrap_or((&text, "0"));
+    let exp: i32 = exp.parse().unwrap_or(0);
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}{}{}{:02}", mantissa, if upper { 'E' } else { 'e' }, sign, exp.abs())
+}
+
+/// `%q`: quoted so a shell reads it back as the same word
+fn quote(arg: &str) -> String {
+    if arg.is_empty() {
+        return "''".to_string();
+    }
+    if arg.chars().all(|c| c.is_ascii_alphanumeric() || "%+,-./:=@_^".contains(c)) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Expand the backslash escapes in `text`, as `echo -e` and `%b` do.
+/// Returns the bytes and whether `\c` asked for output to stop.
+pub fn unescape(text: &str, style: Escapes) -> (Vec<u8>, bool) {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let (expanded, used, stop) = escape(&bytes[i..], style);
+        out.extend_from_slice(&expanded);
+        if stop {
+            return (out, true);
+        }
+        i += used;
+    }
+    (out, false)
+}
+
+/// Where escapes are being expanded, as each place reads octal a little
+/// differently
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Escapes {
+    /// A printf FORMAT: `\NNN`, and `\"` and `\'`
+    Format,
+    /// A `%b` argument: `\0NNN` or `\NNN`
+    Argument,
+    /// `echo -e`: only `\0NNN`, as in GNU echo
+    Echo,
+}
+
+/// The escape sequence at the start of `bytes`: its expansion, how many
+/// bytes it took, and whether it was `\c`. Unknown escapes stay as they are.
+fn escape(bytes: &[u8], style: Escapes) -> (Vec<u8>, usize, bool) {
+    let c = match bytes.get(1) {
+        Some(&c) => c,
+        None => return (vec![b'\\'], 1, false),
+    };
+    let simple = match c {
+        b'\\' => Some(b'\\'),
+        b'a' => Some(0x07),
+        b'b' => Some(0x08),
+        b'e' => Some(0x1B),
+        b'f' => Some(0x0C),
+        b'n' => Some(b'\n'),
+        b'r' => Some(b'\r'),
+        b't' => Some(b'\t'),
+        b'v' => Some(0x0B),
+        b'"' if style == Escapes::Format => Some(b'"'),
+        b'\'' if style == Escapes::Format => Some(b'\''),
+        _ => None,
+    };
+    if let Some(b) = simple {
+        return (vec![b], 2, false);
+    }
+
+    match c {
+        b'c' => (Vec::new(), 2, true),
+        b'1'..=b'7' if style == Escapes::Echo => (vec![b'\\', c], 2, false),
+        b'0'..=b'7' => {
+            // Outside formats, \0 introduces up to three more digits
+            let start = if style != Escapes::Format && c == b'0' { 2 } else { 1 };
+            let count = bytes[start..].iter().take(3).take_while(|b| (b'0'..=b'7').contains(b)).count();
+            let value = bytes[start..start + count].iter().fold(0u32, |v, b| v * 8 + (b - b'0') as u32);
+            (vec![value as u8], start + count, false)
+        }
+        b'x' => {
+            let count = bytes[2..].iter().take(2).take_while(|b| b.is_ascii_hexdigit()).count();
+            if count == 0 {
+                return (b"\\x".to_vec(), 2, false);
+            }
+            let value = u8::from_str_radix(std::str::from_utf8(&bytes[2..2 + count]).unwrap_or("0"), 16).unwrap_or(0);
+            (vec![value], 2 + count, false)
+        }
+        b'u' | b'U' => {
+            let max = if c == b'u' { 4 } else { 8 };
+            let count = bytes[2..].iter().take(max).take_while(|b| b.is_ascii_hexdigit()).count();
+            let code = std::str::from_utf8(&bytes[2..2 + count]).ok().and_then(|h| u32::from_str_radix(h, 16).ok());
+            match code.and_then(char::from_u32) {
+                Some(ch) => (ch.to_string().into_bytes(), 2 + count, false),
+                None => (vec![b'\\', c], 2, false),
+            }
+        }
+        _ => (vec![b'\\', c], 2, false),
+    }
+}
@@ -0,0 +1,18 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+/// Ask a yes/no question on `err` and read the answer from the terminal,
+/// falling back to stdin when there is none. Anything but y/yes is a no.
+pub fn confirm(err: &mut dyn Write, question: &str) -> io::Result<bool> {
+    write!(err, "{} ", question)?;
+    err.flush()?;
+
+    let mut answer = String::new();
+    match File::open("/dev/tty") {
+        Ok(tty) => BufReader::new(tty).read_line(&mut answer)?,
+        Err(_) => io::stdin().lock().read_line(&mut answer)?,
+    };
+
+    let answer = answer.trim();
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
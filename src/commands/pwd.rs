@@ -1,11 +1,85 @@
-pub fn pwd() -> String {
-    // First, try the PWD environment variable
-    if let Ok(pwd_env) = std::env::var("PWD") {
-        return pwd_env;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use crate::commands::options::{Opt, Spec};
+
+static SPEC: Spec = Spec::new(
+    "pwd",
+    "[OPTION]...",
+    "Print the name of the current working directory.",
+    &[
+        Opt::flag('L', "logical", "use PWD from environment, even if it contains symlinks (default)"),
+        Opt::flag('P', "physical", "resolve all symlinks"),
+    ],
+);
+
+pub fn pwd(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+
+    if matches.last_of(&["logical", "physical"]) != Some("physical") {
+        if let Some(logical) = logical() {
+            writeln!(out, "{}", logical)?;
+            return Ok(0);
+        }
+    }
+
+    // getcwd already resolves every symlink
+    match env::current_dir() {
+        Ok(path) => {
+            writeln!(out, "{}", path.display())?;
+            Ok(0)
+        }
+        Err(e) => {
+            writeln!(err, "pwd: cannot access current directory: {}", e)?;
+            Ok(1)
+        }
+    }
+}
+
+/// `PWD`, if it is a usable logical name for the current directory: an
+/// absolute path with no `.` or `..` components that leads to the same
+/// directory as `.`
+pub fn logical() -> Option<String> {
+    let pwd = env::var("PWD").ok()?;
+    if !pwd.starts_with('/') || pwd.split('/').any(|part| part == "." || part == "..") {
+        return None;
+    }
+    let named = fs::metadata(&pwd).ok()?;
+    let actual = fs::metadata(".").ok()?;
+    if named.dev() == actual.dev() && named.ino() == actual.ino() {
+        Some(pwd)
+    } else {
+        None
     }
+}
 
-    // Fallback: try to get from filesystem
-    std::env::current_dir()
-        .map(|path| path.display().to_string())
-        .unwrap_or_else(|_| "pwd: cannot access current directory".to_string())
+/// Replace an inherited `PWD` that doesn't describe the current directory
+/// with the physical path, so `pwd` and `cd ..` start from the truth
+pub fn check_inherited() {
+    if logical().is_none() {
+        if let Ok(cwd) = env::current_dir() {
+            env::set_var("PWD", cwd);
+        }
+    }
+}
+
+/// Resolve `.` and `..` in an absolute path by text alone, as `cd -L` does:
+/// `..` removes the component before it rather than following it on disk
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::Normal(name) => result.push(name),
+            _ => {}
+        }
+    }
+    result
 }
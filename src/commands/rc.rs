@@ -0,0 +1,37 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Settings from `~/.0shellrc`: one `key = value` per line, blank lines
+/// and `#` comments ignored. Keys may repeat; a missing file is empty.
+pub struct Rc {
+    entries: Vec<(String, String)>,
+}
+
+impl Rc {
+    pub fn load() -> Rc {
+        let text = path().and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Rc { entries }
+    }
+
+    /// Every value given for `key`, in file order
+    pub fn values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries.iter().filter(move |(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the last `key` line says yes, true, on or 1
+    pub fn flag(&self, key: &str) -> bool {
+        matches!(self.values(key).last(), Some("yes") | Some("true") | Some("on") | Some("1"))
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".0shellrc"))
+}
@@ -1,96 +1,332 @@
 use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::path::Path;
+use crate::commands::journal::{self, Op};
+use crate::commands::options::{Opt, Spec};
+use crate::commands::prompt;
+use crate::commands::rc::Rc;
+use crate::commands::trash;
 
-pub fn rm(args: &[String]) -> String {
-    if args.is_empty() {
-        return "rm: missing operand".to_string();
-    }
+mod safety;
 
-    let mut recursive = false;
-    let mut files = Vec::new();
+use self::safety::{Safety, Verdict};
 
-    for arg in args {
-        if arg == "-r" {
-            recursive = true;
-        } else {
-            files.push(arg);
+static SPEC: Spec = Spec::new(
+    "rm",
+    "[OPTION]... [FILE]...",
+    "Remove (unlink) the FILE(s).",
+    &[
+        Opt::flag('d', "dir", "remove empty directories"),
+        Opt::flag('f', "force", "ignore nonexistent files and arguments, never prompt"),
+        Opt::flag('i', "", "prompt before every removal"),
+        Opt::flag('I', "", "prompt once before removing more than three files, or when removing recursively"),
+        Opt::optional("interactive", "WHEN", "prompt according to WHEN: never, once (-I), or always (-i)"),
+        Opt::flag('\0', "no-preserve-root", "do not treat '/' specially"),
+        Opt::flag('\0', "no-trash", "delete for good even if ~/.0shellrc sets trash = yes"),
+        Opt::flag('\0', "one-file-system", "when removing recursively, skip any directory that is on a different file system"),
+        Opt::flag('\0', "preserve-root", "do not remove '/' (default)"),
+        Opt::flag('r', "recursive", "remove directories and their contents recursively"),
+        Opt::flag('R', "", "same as -r"),
+        Opt::flag('\0', "trash", "move files to the trash instead of deleting them (see 'trash')"),
+        Opt::flag('v', "verbose", "explain what is being done"),
+    ],
+);
+
+/// When to ask before removing
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Interactive {
+    Never,
+    /// `-I`: once, before removing many files or whole trees
+    Once,
+    /// `-i`: before every removal
+    Always,
+}
+
+pub fn rm(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let interactive = match matches.last_of(&["force", "i", "I", "interactive"]) {
+        Some("i") => Interactive::Always,
+        Some("I") => Interactive::Once,
+        Some("interactive") => match matches.value("interactive").unwrap_or("always") {
+            "never" | "no" | "none" => Interactive::Never,
+            "once" => Interactive::Once,
+            "always" | "yes" => Interactive::Always,
+            when => {
+                writeln!(err, "rm: invalid argument '{}' for '--interactive'", when)?;
+                writeln!(err, "Valid arguments are: never, once, always")?;
+                return Ok(1);
+            }
+        },
+        _ => Interactive::Never,
+    };
+    let mut remover = Remover {
+        force: matches.has("force"),
+        interactive,
+        recursive: matches.has("recursive") || matches.has("R"),
+        dirs: matches.has("dir"),
+        one_file_system: matches.has("one-file-system"),
+        verbose: matches.has("verbose"),
+        trash: match matches.last_of(&["trash", "no-trash"]) {
+            Some(which) => which == "trash",
+            None => Rc::load().flag("trash"),
+        },
+        out,
+        err,
+        status: 0,
+    };
+    let preserve_root = matches.last_of(&["preserve-root", "no-preserve-root"]) != Some("no-preserve-root");
+    let files = &matches.operands;
+
+    if files.is_empty() {
+        if remover.force {
+            return Ok(0);
         }
+        writeln!(remover.err, "rm: missing operand")?;
+        writeln!(remover.err, "Try 'rm --help' for more information.")?;
+        return Ok(1);
     }
 
-    if files.is_empty() {
-        return "rm: missing operand".to_string();
+    if interactive == Interactive::Once && (files.len() > 3 || remover.recursive) {
+        let question = match (files.len(), remover.recursive) {
+            (1, true) => "rm: remove 1 argument recursively?".to_string(),
+            (n, true) => format!("rm: remove {} arguments recursively?", n),
+            (n, false) => format!("rm: remove {} arguments?", n),
+        };
+        if !prompt::confirm(remover.err, &question)? {
+            return Ok(0);
+        }
     }
 
-    let mut messages = Vec::new();
+    let root = fs::metadata("/").ok();
+    let safety = Safety::load();
 
     for file in files {
-        // ✅ refuse to remove dangerous paths like ".", "..", "/", "./", "../", ".///"
-        if is_dangerous_path(file) {
-            messages.push(format!("rm: refusing to remove '{}'", file));
+        let path = Path::new(file);
+
+        if ends_in_dots(file) {
+            writeln!(remover.err, "rm: refusing to remove '.' or '..' directory: skipping '{}'", file)?;
+            remover.status = 1;
             continue;
         }
 
-        let path = Path::new(file);
+        let meta = match fs::symlink_metadata(path) {
+            Ok(meta) => meta,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                if !remover.force {
+                    writeln!(remover.err, "rm: cannot remove '{}': No such file or directory", file)?;
+                    remover.status = 1;
+                }
+                continue;
+            }
+            Err(e) => {
+                writeln!(remover.err, "rm: cannot remove '{}': {}", file, e)?;
+                remover.status = 1;
+                continue;
+            }
+        };
 
-        if !path.exists() {
-            messages.push(format!(
-                "rm: cannot remove '{}': No such file or directory",
-                file
-            ));
+        if preserve_root && remover.recursive && root.as_ref().is_some_and(|root| root.dev() == meta.dev() && root.ino() == meta.ino()) {
+            writeln!(remover.err, "rm: it is dangerous to operate recursively on '{}'", file)?;
+            writeln!(remover.err, "rm: use --no-preserve-root to override this failsafe")?;
+            remover.status = 1;
             continue;
         }
 
-        if path.is_dir() {
-            if recursive {
-                match fs::remove_dir_all(path) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        messages.push(format!("rm: cannot remove directory '{}': {}", file, e))
-                    }
+        match safety.check(path, &meta, remover.recursive) {
+            Verdict::Allow => {}
+            Verdict::Refuse(reason) => {
+                writeln!(remover.err, "rm: refusing to remove '{}': {}", file, reason)?;
+                remover.status = 1;
+                continue;
+            }
+            Verdict::Confirm(concerns) => {
+                let question = format!("rm: '{}' {}; remove it?", file, concerns.join(" and "));
+                if !remover.force && !prompt::confirm(remover.err, &question)? {
+                    continue;
                 }
-            } else {
-                messages.push(format!("rm: cannot remove '{}': Is a directory", file));
             }
+        }
+
+        if remover.trash {
+            remover.discard(path, &meta)?;
         } else {
-            match fs::remove_file(path) {
-                Ok(_) => {}
-                Err(e) => messages.push(format!("rm: cannot remove '{}': {}", file, e)),
-            }
+            remover.remove(path, &meta, meta.dev())?;
         }
     }
 
-    if messages.is_empty() {
-        String::new()
-    } else {
-        messages.join("\n")
-    }
+    Ok(remover.status)
 }
 
-fn is_dangerous_path(path: &str) -> bool {
-    let path = Path::new(path);
+struct Remover<'a> {
+    force: bool,
+    interactive: Interactive,
+    recursive: bool,
+    /// `-d`: rmdir empty directories
+    dirs: bool,
+    one_file_system: bool,
+    verbose: bool,
+    /// Move operands to the trash rather than deleting them
+    trash: bool,
+    out: &'a mut dyn Write,
+    err: &'a mut dyn Write,
+    status: i32,
+}
 
-    // Try to canonicalize the path
-    if let Ok(canonical) = path.canonicalize() {
-        // Refuse root
-        if canonical == Path::new("/") {
-            return true;
+impl Remover<'_> {
+    /// Remove `path` and, with -r, everything under it. Returns whether it
+    /// is gone; failures are reported as they happen.
+    fn remove(&mut self, path: &Path, meta: &fs::Metadata, dev: u64) -> io::Result<bool> {
+        if meta.is_dir() {
+            if self.recursive {
+                return self.remove_tree(path, meta, dev);
+            }
+            if !self.dirs {
+                return self.fail(path, "Is a directory");
+            }
+            if !self.ask(&format!("rm: remove directory '{}'?", path.display()))? {
+                return Ok(false);
+            }
+            return match fs::remove_dir(path) {
+                Ok(()) => self.removed("removed directory", path),
+                Err(e) => self.fail(path, &e.to_string()),
+            };
         }
 
-        // Refuse current dir
-        if canonical == std::env::current_dir().unwrap() {
-            return true;
+        if !self.ask(&format!("rm: remove {} '{}'?", describe(meta), path.display()))? {
+            return Ok(false);
+        }
+        match fs::remove_file(path) {
+            Ok(()) => self.removed("removed", path),
+            Err(e) => self.fail(path, &e.to_string()),
         }
+    }
 
-        // Optionally, refuse if path escapes current working directory
-        let cwd = std::env::current_dir().unwrap();
-        if !canonical.starts_with(&cwd) {
-            return true;
+    /// `dev` is the device of the directory `path` sits in, to spot mounts
+    fn remove_tree(&mut self, path: &Path, meta: &fs::Metadata, dev: u64) -> io::Result<bool> {
+        if meta.dev() != dev {
+            if self.one_file_system {
+                writeln!(self.err, "rm: skipping '{}', since it's on a different device", path.display())?;
+                self.status = 1;
+                return Ok(false);
+            }
+            let question = format!("rm: '{}' is a mount point; descend into it?", path.display());
+            if !self.force && !prompt::confirm(self.err, &question)? {
+                return Ok(false);
+            }
         }
 
-        // Otherwise, safe
-        false
+        let mut entries = match fs::read_dir(path) {
+            Ok(entries) => entries.peekable(),
+            Err(e) => return self.fail(path, &e.to_string()),
+        };
+        if entries.peek().is_some() && !self.ask(&format!("rm: descend into directory '{}'?", path.display()))? {
+            return Ok(false);
+        }
+
+        // A child that stays keeps the directory too, without a second error
+        let mut emptied = true;
+        for entry in entries {
+            let child = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    self.fail(path, &e.to_string())?;
+                    emptied = false;
+                    continue;
+                }
+            };
+            emptied &= match fs::symlink_metadata(&child) {
+                Ok(child_meta) => self.remove(&child, &child_meta, meta.dev())?,
+                Err(e) => self.fail(&child, &e.to_string())?,
+            };
+        }
+        if !emptied {
+            return Ok(false);
+        }
+
+        if !self.ask(&format!("rm: remove directory '{}'?", path.display()))? {
+            return Ok(false);
+        }
+        match fs::remove_dir(path) {
+            Ok(()) => self.removed("removed directory", path),
+            Err(e) => self.fail(path, &e.to_string()),
+        }
+    }
+
+    /// Move `path` to the trash in one piece. Directories still need -r,
+    /// or -d if they are empty.
+    fn discard(&mut self, path: &Path, meta: &fs::Metadata) -> io::Result<bool> {
+        if meta.is_dir() && !self.recursive {
+            if !self.dirs {
+                return self.fail(path, "Is a directory");
+            }
+            if !fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none()) {
+                return self.fail(path, "Directory not empty");
+            }
+        }
+        if !self.ask(&format!("rm: move {} '{}' to the trash?", describe(meta), path.display()))? {
+            return Ok(false);
+        }
+        match trash::put(path) {
+            Ok(trashed) => {
+                journal::record(Op::Trash { original: journal::absolute(path), trashed });
+                self.removed("trashed", path)
+            }
+            Err(e) => self.fail(path, &e.to_string()),
+        }
+    }
+
+    /// Ask `question` under -i; everything is a yes otherwise
+    fn ask(&mut self, question: &str) -> io::Result<bool> {
+        if self.interactive != Interactive::Always {
+            return Ok(true);
+        }
+        prompt::confirm(self.err, question)
+    }
+
+    fn removed(&mut self, what: &str, path: &Path) -> io::Result<bool> {
+        if self.verbose {
+            writeln!(self.out, "{} '{}'", what, path.display())?;
+        }
+        Ok(true)
+    }
+
+    fn fail(&mut self, path: &Path, reason: &str) -> io::Result<bool> {
+        writeln!(self.err, "rm: cannot remove '{}': {}", path.display(), reason)?;
+        self.status = 1;
+        Ok(false)
+    }
+}
+
+/// Whether the last component of `file` is `.` or `..`
+fn ends_in_dots(file: &str) -> bool {
+    let last = file.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    last == "." || last == ".."
+}
+
+/// How prompts name a file's type
+fn describe(meta: &fs::Metadata) -> &'static str {
+    let ft = meta.file_type();
+    if ft.is_dir() {
+        "directory"
+    } else if ft.is_symlink() {
+        "symbolic link"
+    } else if ft.is_file() && meta.len() == 0 {
+        "regular empty file"
+    } else if ft.is_file() {
+        "regular file"
+    } else if ft.is_fifo() {
+        "fifo"
+    } else if ft.is_socket() {
+        "socket"
+    } else if ft.is_block_device() {
+        "block special file"
+    } else if ft.is_char_device() {
+        "character special file"
     } else {
-        // If canonicalization fails, treat as safe for now (or log warning)
-        false
+        "file"
     }
 }
@@ -0,0 +1,149 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::commands::rc::Rc;
+use crate::commands::trash::absolute;
+
+/// Protected unless the rc file says otherwise; `$HOME` is added too
+const PROTECTED: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib64", "/opt", "/proc", "/root", "/sbin", "/srv", "/sys",
+    "/usr", "/var",
+];
+
+/// What rm guards against beyond `--preserve-root`: removing the current
+/// directory or anything holding it, and the protected paths (the
+/// defaults plus each `protect = PATH` in `~/.0shellrc`, or only the
+/// latter with `protect-defaults = no`). Mount points and paths outside
+/// the current directory need confirming.
+///
+/// Paths are judged both by where they are, with the parent resolved but
+/// a final symlink left alone, and by where they point. Protected paths
+/// are kept both ways too, so a protected symlink such as `/bin` on a
+/// merged-usr system guards itself as well as `/usr/bin`.
+pub struct Safety {
+    cwd: Option<PathBuf>,
+    protected: Vec<PathBuf>,
+}
+
+/// What to do about one operand
+pub enum Verdict {
+    Allow,
+    Refuse(String),
+    /// Go ahead once the user agrees to each concern
+    Confirm(Vec<&'static str>),
+}
+
+impl Safety {
+    pub fn load() -> Safety {
+        let rc = Rc::load();
+        let mut protected: Vec<PathBuf> = Vec::new();
+        if rc.values("protect-defaults").last() != Some("no") {
+            protected.extend(PROTECTED.iter().map(PathBuf::from));
+            protected.extend(env::var_os("HOME").map(PathBuf::from));
+        }
+        protected.extend(rc.values("protect").map(PathBuf::from));
+
+        Safety::new(env::current_dir().ok(), protected)
+    }
+
+    fn new(cwd: Option<PathBuf>, paths: Vec<PathBuf>) -> Safety {
+        let protected = paths.iter().flat_map(|path| forms(path).unwrap_or_else(|| vec![path.clone()])).collect();
+        Safety { cwd, protected }
+    }
+
+    pub fn check(&self, path: &Path, meta: &fs::Metadata, recursive: bool) -> Verdict {
+        // Unresolvable paths can't be removed either; rm reports why
+        let resolved = match absolute(path) {
+            Ok(resolved) => resolved,
+            Err(_) => return Verdict::Allow,
+        };
+        let forms = forms(path).unwrap_or_else(|| vec![resolved.clone()]);
+
+        if let Some(cwd) = &self.cwd {
+            if meta.is_dir() && cwd.starts_with(&resolved) {
+                return Verdict::Refuse("it contains the current directory".to_string());
+            }
+        }
+        // Recursion would reach a protected path below this one too
+        let hits = |p: &PathBuf| forms.iter().any(|form| p == form || (recursive && meta.is_dir() && p.starts_with(form)));
+        if self.protected.iter().any(hits) {
+            return Verdict::Refuse("it is a protected path".to_string());
+        }
+
+        let mut concerns = Vec::new();
+        if is_mount_point(&resolved, meta) {
+            concerns.push("is a mount point");
+        }
+        if self.cwd.as_ref().is_some_and(|cwd| !resolved.starts_with(cwd)) {
+            concerns.push("is outside the current directory");
+        }
+        if concerns.is_empty() {
+            Verdict::Allow
+        } else {
+            Verdict::Confirm(concerns)
+        }
+    }
+}
+
+/// `path` made absolute as it stands, and also with a final symlink
+/// followed when that leads somewhere else
+fn forms(path: &Path) -> Option<Vec<PathBuf>> {
+    let literal = absolute(path).ok()?;
+    let mut forms = vec![literal.clone()];
+    if let Ok(canonical) = path.canonicalize() {
+        if canonical != literal {
+            forms.push(canonical);
+        }
+    }
+    Some(forms)
+}
+
+/// A directory on a different device from its parent, or the root
+fn is_mount_point(resolved: &Path, meta: &fs::Metadata) -> bool {
+    if !meta.is_dir() {
+        return false;
+    }
+    match resolved.parent() {
+        Some(parent) => fs::metadata(parent).is_ok_and(|parent| parent.dev() != meta.dev()),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::process;
+
+    fn refused(safety: &Safety, path: &Path) -> bool {
+        let meta = fs::symlink_metadata(path).unwrap();
+        matches!(safety.check(path, &meta, false), Verdict::Refuse(_))
+    }
+
+    #[test]
+    fn protected_symlinks() {
+        let root = env::temp_dir().join(format!("0shell-safety-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("real")).unwrap();
+        fs::create_dir_all(root.join("other")).unwrap();
+        symlink(root.join("real"), root.join("link")).unwrap();
+        symlink(root.join("other"), root.join("alias")).unwrap();
+
+        // The protected link itself, and what it points to
+        let safety = Safety::new(None, vec![root.join("link")]);
+        assert!(refused(&safety, &root.join("link")));
+        assert!(refused(&safety, &root.join("real")));
+
+        // A link leading to a protected directory
+        let safety = Safety::new(None, vec![root.join("other")]);
+        assert!(refused(&safety, &root.join("alias")));
+        assert!(!refused(&safety, &root.join("real")));
+
+        // /bin, which is a link to usr/bin on merged-usr systems
+        assert!(refused(&Safety::new(None, vec![PathBuf::from("/bin")]), Path::new("/bin")));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
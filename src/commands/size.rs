@@ -0,0 +1,90 @@
+/// How byte counts are shown: `-h`/`--si` style, or whole units of a block size
+#[derive(Clone, PartialEq, Eq)]
+pub enum Scale {
+    /// Powers of `base` (1024 or 1000) with a unit letter, e.g. `1.5K`
+    Human { base: u64 },
+    /// Rounded up to a multiple of `size`, followed by `suffix`
+    Blocks { size: u64, suffix: String },
+}
+
+impl Scale {
+    pub const fn bytes() -> Scale {
+        Scale::Blocks { size: 1, suffix: String::new() }
+    }
+
+    pub fn format(&self, bytes: u64) -> String {
+        match self {
+            Scale::Human { base } => human(bytes, *base),
+            Scale::Blocks { size, suffix } => format!("{}{}", bytes.div_ceil(*size), suffix),
+        }
+    }
+}
+
+/// GNU-style human-readable size: one decimal below 10, rounded up
+pub fn human(bytes: u64, base: u64) -> String {
+    let units: &[&str] = if base == 1000 {
+        &["k", "M", "G", "T", "P", "E"]
+    } else {
+        &["K", "M", "G", "T", "P", "E"]
+    };
+    if bytes < base {
+        return bytes.to_string();
+    }
+
+    let mut value = bytes as f64 / base as f64;
+    let mut unit = 0;
+    while value >= base as f64 && unit + 1 < units.len() {
+        value /= base as f64;
+        unit += 1;
+    }
+
+    if value < 10.0 {
+        let rounded = (value * 10.0).ceil() / 10.0;
+        if rounded < 10.0 {
+            return format!("{:.1}{}", rounded, units[unit]);
+        }
+        value = rounded;
+    }
+    let rounded = value.ceil();
+    if rounded >= base as f64 && unit + 1 < units.len() {
+        return format!("1.0{}", units[unit + 1]);
+    }
+    format!("{}{}", rounded as u64, units[unit])
+}
+
+/// Parse a `--block-size` argument: `1024`, `K`, `4K`, `KB`, `KiB`, `M`...
+/// Unit letters are powers of 1024; a trailing `B` without `i` makes them
+/// powers of 1000. Sizes given with a unit keep it as a suffix on output.
+pub fn parse_block_size(arg: &str) -> Option<Scale> {
+    match arg {
+        "human-readable" => return Some(Scale::Human { base: 1024 }),
+        "si" => return Some(Scale::Human { base: 1000 }),
+        _ => {}
+    }
+
+    let digits_end = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (number, unit) = arg.split_at(digits_end);
+    let count: u64 = if number.is_empty() { 1 } else { number.parse().ok()? };
+
+    let mut chars = unit.chars();
+    let (multiplier, suffix) = match chars.next() {
+        None => (1, String::new()),
+        Some(letter) => {
+            let exponent = "KMGTPE".find(letter.to_ascii_uppercase())? as u32 + 1;
+            let base: u64 = match chars.as_str() {
+                "" | "iB" => 1024,
+                "B" => 1000,
+                _ => return None,
+            };
+            (base.checked_pow(exponent)?, unit.to_string())
+        }
+    };
+
+    let size = count.checked_mul(multiplier)?;
+    if size == 0 {
+        return None;
+    }
+    // GNU only shows the unit when no explicit count was given
+    let suffix = if number.is_empty() { suffix } else { String::new() };
+    Some(Scale::Blocks { size, suffix })
+}
@@ -0,0 +1,412 @@
+use chrono::{Duration, Local, NaiveDateTime};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use crate::commands::options::{Opt, Spec};
+
+static SPEC: Spec = Spec::new(
+    "trash",
+    "list | restore NAME... | empty [--older-than AGE]",
+    "Show, restore or purge files moved to the trash by 'rm --trash'.\n\
+     NAME is an original path or a name in the trash; AGE is a number of\n\
+     days, or a number followed by s, m, h, d or w.",
+    &[Opt::value('\0', "older-than", "AGE", "with empty, only purge what was trashed at least AGE ago")],
+);
+
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+pub fn trash(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let (command, names) = match matches.operands.split_first() {
+        Some((command, names)) => (command.as_str(), names),
+        None => {
+            writeln!(err, "trash: missing command")?;
+            writeln!(err, "Try 'trash --help' for more information.")?;
+            return Ok(2);
+        }
+    };
+
+    match command {
+        "list" => {
+            for entry in entries() {
+                // The name tells apart several deletions of the same path
+                writeln!(out, "{}  {}  {}", entry.deleted.replace('T', " "), entry.name.to_string_lossy(), entry.original.display())?;
+            }
+            Ok(0)
+        }
+        "restore" => {
+            if names.is_empty() {
+                writeln!(err, "trash: missing file operand")?;
+                return Ok(1);
+            }
+            let mut status = 0;
+            for name in names {
+                if let Err(message) = restore_named(name) {
+                    writeln!(err, "trash: {}", message)?;
+                    status = 1;
+                }
+            }
+            Ok(status)
+        }
+        "empty" => {
+            let older_than = match matches.value("older-than").map(parse_age) {
+                None => None,
+                Some(Some(age)) => Some(age),
+                Some(None) => {
+                    writeln!(err, "trash: invalid age '{}'", matches.value("older-than").unwrap_or_default())?;
+                    return Ok(1);
+                }
+            };
+            let cutoff = older_than.map(|age| Local::now().naive_local() - age);
+            let mut status = 0;
+            for entry in entries() {
+                // Entries with unreadable dates are only purged by a plain empty
+                let expired = match cutoff {
+                    None => true,
+                    Some(cutoff) => entry.deleted_at().is_some_and(|at| at <= cutoff),
+                };
+                if !expired {
+                    continue;
+                }
+                if let Err(e) = entry.purge() {
+                    writeln!(err, "trash: cannot remove '{}': {}", entry.file().display(), e)?;
+                    status = 1;
+                }
+            }
+            Ok(status)
+        }
+        _ => {
+            writeln!(err, "trash: unknown command '{}'", command)?;
+            writeln!(err, "Try 'trash --help' for more information.")?;
+            Ok(2)
+        }
+    }
+}
+
+/// One trashed file: `files/NAME` in a trash directory, described by
+/// `info/NAME.trashinfo`
+pub struct Entry {
+    dir: PathBuf,
+    pub name: OsString,
+    /// Where it was before, made absolute
+    pub original: PathBuf,
+    /// `DeletionDate` as written, `YYYY-MM-DDThh:mm:ss` in local time
+    pub deleted: String,
+}
+
+impl Entry {
+    pub fn file(&self) -> PathBuf {
+        self.dir.join("files").join(&self.name)
+    }
+
+    fn info(&self) -> PathBuf {
+        self.dir.join("info").join(info_name(&self.name))
+    }
+
+    pub fn deleted_at(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(&self.deleted, DATE_FORMAT).ok()
+    }
+
+    /// Put the file back where it came from, which must be free
+    pub fn restore(&self) -> io::Result<()> {
+        if fs::symlink_metadata(&self.original).is_ok() {
+            return Err(io::Error::from_raw_os_error(libc::EEXIST));
+        }
+        fs::rename(self.file(), &self.original)?;
+        fs::remove_file(self.info())
+    }
+
+    /// Delete the file for good
+    pub fn purge(&self) -> io::Result<()> {
+        let file = self.file();
+        let removed = match fs::symlink_metadata(&file) {
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(&file),
+            Ok(_) => fs::remove_file(&file),
+            Err(_) => Ok(()),
+        };
+        removed.and_then(|_| fs::remove_file(self.info()))
+    }
+}
+
+/// Move `path` to the trash of its filesystem: the home trash when it
+/// shares a device with it, `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid`
+/// otherwise. Returns where it ended up.
+pub fn put(path: &Path) -> io::Result<PathBuf> {
+    let meta = fs::symlink_metadata(path)?;
+    let original = absolute(path)?;
+
+    let home = home_trash().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    create_trash_dir(&home)?;
+    let (dir, recorded) = if fs::metadata(&home)?.dev() == meta.dev() {
+        (home, original.clone())
+    } else {
+        // Per-mount trashes record paths relative to the mount
+        let top = mount_top(&original, meta.dev());
+        let dir = mount_trash(&top)?;
+        let relative = original.strip_prefix(&top).map(Path::to_path_buf).unwrap_or_else(|_| original.clone());
+        (dir, relative)
+    };
+
+    let base = original.file_name().map(OsStr::to_os_string).unwrap_or_else(|| OsString::from("file"));
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode(recorded.as_os_str().as_bytes()),
+        Local::now().format(DATE_FORMAT)
+    );
+
+    // Creating the .trashinfo exclusively is what reserves a name
+    let mut n = 1;
+    loop {
+        let mut name = base.clone();
+        if n > 1 {
+            name.push(format!(".{}", n));
+        }
+        n += 1;
+        let target = dir.join("files").join(&name);
+        let info_path = dir.join("info").join(info_name(&name));
+        if fs::symlink_metadata(&target).is_ok() {
+            continue;
+        }
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let moved = file.write_all(info.as_bytes()).and_then(|_| fs::rename(path, &target));
+        if let Err(e) = moved {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        return Ok(target);
+    }
+}
+
+/// Move `trashed`, as returned by `put`, back to `original` and drop its
+/// `.trashinfo`
+pub fn untrash(trashed: &Path, original: &Path) -> io::Result<()> {
+    let (dir, name) = match (trashed.parent().and_then(Path::parent), trashed.file_name()) {
+        (Some(dir), Some(name)) => (dir, name),
+        _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+    };
+    fs::rename(trashed, original)?;
+    fs::remove_file(dir.join("info").join(info_name(name)))
+}
+
+/// Everything in every trash we can find, oldest first
+pub fn entries() -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for (dir, top) in trash_dirs() {
+        let infos = match fs::read_dir(dir.join("info")) {
+            Ok(infos) => infos,
+            Err(_) => continue,
+        };
+        for info in infos.flatten() {
+            let file_name = info.file_name();
+            let name = match file_name.as_bytes().strip_suffix(b".trashinfo") {
+                Some(name) => OsStr::from_bytes(name).to_os_string(),
+                None => continue,
+            };
+            let text = match fs::read_to_string(info.path()) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            let mut original = None;
+            let mut deleted = String::new();
+            for line in text.lines() {
+                if let Some(value) = line.strip_prefix("Path=") {
+                    original = Some(PathBuf::from(OsString::from_vec(decode(value))));
+                } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+                    deleted = value.to_string();
+                }
+            }
+            let original = match (original, &top) {
+                (Some(path), Some(top)) if path.is_relative() => top.join(path),
+                (Some(path), _) => path,
+                (None, _) => continue,
+            };
+            entries.push(Entry { dir: dir.clone(), name, original, deleted });
+        }
+    }
+    entries.sort_by(|a, b| a.deleted.cmp(&b.deleted));
+    entries
+}
+
+/// Restore the newest entry matching `name`, either its name in the trash
+/// or the path it was trashed from
+fn restore_named(name: &str) -> Result<(), String> {
+    let wanted = absolute(Path::new(name)).ok();
+    let entry = entries()
+        .into_iter()
+        .rev()
+        .find(|entry| entry.name == OsStr::new(name) || Some(&entry.original) == wanted.as_ref())
+        .ok_or_else(|| format!("'{}' is not in the trash", name))?;
+    entry.restore().map_err(|e| format!("cannot restore '{}' to '{}': {}", name, entry.original.display(), e))
+}
+
+fn info_name(name: &OsStr) -> OsString {
+    let mut info = name.to_os_string();
+    info.push(".trashinfo");
+    info
+}
+
+/// `$XDG_DATA_HOME/Trash`, by default `~/.local/share/Trash`
+fn home_trash() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(data) if !data.is_empty() => Some(PathBuf::from(data).join("Trash")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/Trash")),
+    }
+}
+
+fn create_trash_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder.create(dir.join("files"))?;
+    builder.create(dir.join("info"))
+}
+
+/// The trash for files under mount point `top`. A shared `.Trash` only
+/// counts if it is a real sticky directory, as the spec asks.
+fn mount_trash(top: &Path) -> io::Result<PathBuf> {
+    let uid = current_uid();
+    let shared = top.join(".Trash");
+    if let Ok(meta) = fs::symlink_metadata(&shared) {
+        if meta.is_dir() && meta.permissions().mode() & 0o1000 != 0 {
+            let dir = shared.join(uid.to_string());
+            if create_trash_dir(&dir).is_ok() {
+                return Ok(dir);
+            }
+        }
+    }
+    let dir = top.join(format!(".Trash-{}", uid));
+    create_trash_dir(&dir)?;
+    Ok(dir)
+}
+
+/// The home trash plus any per-mount trashes of ours, each with the
+/// mount point relative paths in it start from
+fn trash_dirs() -> Vec<(PathBuf, Option<PathBuf>)> {
+    let mut dirs: Vec<(PathBuf, Option<PathBuf>)> = home_trash().into_iter().map(|dir| (dir, None)).collect();
+    let uid = current_uid();
+    let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    for line in mounts.lines() {
+        let top = match line.split_whitespace().nth(1) {
+            Some(field) => PathBuf::from(OsString::from_vec(unescape_mount(field))),
+            None => continue,
+        };
+        for dir in [top.join(".Trash").join(uid.to_string()), top.join(format!(".Trash-{}", uid))] {
+            if dir.join("info").is_dir() && !dirs.iter().any(|(known, _)| *known == dir) {
+                dirs.push((dir, Some(top.clone())));
+            }
+        }
+    }
+    dirs
+}
+
+/// The highest directory above `path` still on device `dev`
+fn mount_top(path: &Path, dev: u64) -> PathBuf {
+    let mut top = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+    while let Some(parent) = top.parent() {
+        match fs::metadata(parent) {
+            Ok(meta) if meta.dev() == dev => top = parent.to_path_buf(),
+            _ => break,
+        }
+    }
+    top
+}
+
+/// `path` made absolute with its parent resolved but not its last
+/// component, so a symlink stands for itself
+pub fn absolute(path: &Path) -> io::Result<PathBuf> {
+    let name = match path.file_name() {
+        Some(name) => name,
+        None => return path.canonicalize(),
+    };
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(parent.canonicalize()?.join(name))
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions
+    unsafe { libc::getuid() }
+}
+
+/// Percent-encode a path for a `.trashinfo` file, keeping `/`
+fn encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// /proc/self/mounts writes spaces and the like as `\040` octal escapes
+fn unescape_mount(field: &str) -> Vec<u8> {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|o| std::str::from_utf8(o).ok()).and_then(|o| u8::from_str_radix(o, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(b)) => {
+                out.push(b);
+                i += 4;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// `30d`, `12h`, `90m`, `45s`, `2w`, or a bare number of days
+fn parse_age(arg: &str) -> Option<Duration> {
+    let (number, unit) = match arg.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => arg.split_at(i),
+        None => (arg, "d"),
+    };
+    let n: i64 = number.parse().ok()?;
+    match unit {
+        "s" => Duration::try_seconds(n),
+        "m" => Duration::try_minutes(n),
+        "h" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        "w" => Duration::try_weeks(n),
+        _ => None,
+    }
+}
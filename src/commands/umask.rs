@@ -0,0 +1,72 @@
+use std::io::{self, Write};
+use crate::commands::mode;
+use crate::commands::options::{Opt, Spec};
+
+static SPEC: Spec = Spec::new(
+    "umask",
+    "[-p] [-S] [MODE]",
+    "Show or set the file mode creation mask. MODE is octal, or symbolic\n\
+     like chmod's, naming the permissions new files should keep.",
+    &[
+        Opt::flag('p', "", "print the mask as a command that sets it"),
+        Opt::flag('S', "", "print the mask as symbolic permissions"),
+    ],
+);
+
+pub fn umask(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+    let matches = match SPEC.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(out, err),
+    };
+    let mask = current_umask();
+
+    let arg = match matches.operands.as_slice() {
+        [] => {
+            let shown = if matches.has("S") { mode::symbolic(!mask & 0o777) } else { format!("{:04o}", mask) };
+            if matches.has("p") {
+                let flag = if matches.has("S") { "-S " } else { "" };
+                writeln!(out, "umask {}{}", flag, shown)?;
+            } else {
+                writeln!(out, "{}", shown)?;
+            }
+            return Ok(0);
+        }
+        [arg] => arg,
+        [_, extra, ..] => {
+            writeln!(err, "umask: extra operand '{}'", extra)?;
+            return Ok(2);
+        }
+    };
+
+    // Symbolic modes say what to keep, so they work on the complement
+    let new_mask = if arg.bytes().all(|b| b.is_ascii_digit()) {
+        mode::parse(arg, 0, 0, true).filter(|m| *m <= 0o777)
+    } else {
+        mode::parse(arg, !mask & 0o777, 0, true).map(|perms| !perms & 0o777)
+    };
+    let new_mask = match new_mask {
+        Some(new_mask) => new_mask,
+        None => {
+            writeln!(err, "umask: invalid mode '{}'", arg)?;
+            return Ok(1);
+        }
+    };
+    // SAFETY: umask has no preconditions
+    unsafe {
+        libc::umask(new_mask as libc::mode_t);
+    }
+    if matches.has("S") {
+        writeln!(out, "{}", mode::symbolic(!new_mask & 0o777))?;
+    }
+    Ok(0)
+}
+
+/// The process umask, which can only be read by setting it
+pub fn current_umask() -> u32 {
+    // SAFETY: umask has no preconditions; the old value is put straight back
+    unsafe {
+        let mask = libc::umask(0o022);
+        libc::umask(mask);
+        mask as u32
+    }
+}
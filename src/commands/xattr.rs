@@ -0,0 +1,180 @@
+use std::ffi::{CString, OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+
+/// SELinux label, which `ls` reports with `.` rather than `+`
+pub const SELINUX: &str = "security.selinux";
+
+/// POSIX ACLs, which `ls` reports with `+`
+const ACL_ACCESS: &str = "system.posix_acl_access";
+const ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// Tags of the ACL entries that only restate the mode bits
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_OTHER: u16 = 0x20;
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+}
+
+fn c_name(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+}
+
+/// Names of the extended attributes on `path`. Symlinks are not followed.
+pub fn list(path: &Path) -> io::Result<Vec<OsString>> {
+    let c_path = c_path(path)?;
+
+    loop {
+        // SAFETY: a null buffer of size 0 only asks for the needed length
+        let len = unsafe { libc::llistxattr(c_path.as_ptr(), std::ptr::null_mut(), 0) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut buf = vec![0u8; len as usize];
+        // SAFETY: buf is valid for buf.len() bytes
+        let got = unsafe { libc::llistxattr(c_path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if got < 0 {
+            let e = io::Error::last_os_error();
+            // The list grew between the two calls; ask again
+            if e.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(e);
+        }
+        buf.truncate(got as usize);
+
+        return Ok(buf
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| OsString::from_vec(name.to_vec()))
+            .collect());
+    }
+}
+
+/// Value of the attribute `name` on `path`. Symlinks are not followed.
+pub fn get(path: &Path, name: &OsStr) -> io::Result<Vec<u8>> {
+    let c_path = c_path(path)?;
+    let c_name = c_name(name)?;
+
+    loop {
+        // SAFETY: a null buffer of size 0 only asks for the needed length
+        let len = unsafe { libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0u8; len as usize];
+        // SAFETY: buf is valid for buf.len() bytes
+        let got = unsafe { libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if got < 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(e);
+        }
+        buf.truncate(got as usize);
+        return Ok(buf);
+    }
+}
+
+/// Set the attribute `name` on `path`. Symlinks are not followed.
+pub fn set(path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    let c_name = c_name(name)?;
+
+    // SAFETY: value is valid for value.len() bytes
+    let rc = unsafe { libc::lsetxattr(c_path.as_ptr(), c_name.as_ptr(), value.as_ptr() as *const libc::c_void, value.len(), 0) };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// What the `ls -l` mode column should be followed by, as with GNU ls:
+/// `+` for a non-trivial POSIX ACL, `.` for an SELinux label, nothing
+/// otherwise. Other attributes don't count. Filesystems without xattr
+/// support simply have none.
+pub fn indicator(path: &Path, mode: u32) -> Option<char> {
+    let names = list(path).ok()?;
+    let has = |wanted: &str| names.iter().any(|n| n == wanted);
+    let acl = has(ACL_DEFAULT)
+        || (has(ACL_ACCESS) && get(path, OsStr::new(ACL_ACCESS)).is_ok_and(|value| !acl_is_trivial(&value, mode)));
+    if acl {
+        Some('+')
+    } else if has(SELINUX) {
+        Some('.')
+    } else {
+        None
+    }
+}
+
+/// Whether an access ACL holds only owner, group and other entries that
+/// match `mode`, and so says nothing the mode bits don't. The value is a
+/// 4-byte version, then 8-byte entries of tag, permissions and id, all
+/// little-endian.
+fn acl_is_trivial(value: &[u8], mode: u32) -> bool {
+    if value.len() < 4 || !(value.len() - 4).is_multiple_of(8) {
+        return false;
+    }
+    value[4..].chunks(8).all(|entry| {
+        let tag = u16::from_le_bytes([entry[0], entry[1]]);
+        let perm = u16::from_le_bytes([entry[2], entry[3]]) as u32;
+        match tag {
+            ACL_USER_OBJ => perm == (mode >> 6) & 7,
+            ACL_GROUP_OBJ => perm == (mode >> 3) & 7,
+            ACL_OTHER => perm == mode & 7,
+            _ => false,
+        }
+    })
+}
+
+/// Render an attribute value the way `getfattr` does: quoted text when it
+/// is printable, hex otherwise
+pub fn display_value(value: &[u8]) -> String {
+    // Text values are usually NUL-terminated
+    let text = value.strip_suffix(&[0]).unwrap_or(value);
+    match std::str::from_utf8(text) {
+        Ok(s) if !s.chars().any(|c| c.is_control()) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        _ => {
+            let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("0x{}", hex)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl(entries: &[(u16, u16)]) -> Vec<u8> {
+        let mut value = 2u32.to_le_bytes().to_vec();
+        for &(tag, perm) in entries {
+            value.extend_from_slice(&tag.to_le_bytes());
+            value.extend_from_slice(&perm.to_le_bytes());
+            value.extend_from_slice(&u32::MAX.to_le_bytes());
+        }
+        value
+    }
+
+    #[test]
+    fn mode_only_acls_are_trivial() {
+        let value = acl(&[(ACL_USER_OBJ, 6), (ACL_GROUP_OBJ, 4), (ACL_OTHER, 4)]);
+        assert!(acl_is_trivial(&value, 0o644));
+        assert!(!acl_is_trivial(&value, 0o600));
+    }
+
+    #[test]
+    fn named_entries_are_not() {
+        let value = acl(&[(ACL_USER_OBJ, 6), (0x02, 4), (ACL_GROUP_OBJ, 4), (0x10, 4), (ACL_OTHER, 4)]);
+        assert!(!acl_is_trivial(&value, 0o644));
+        assert!(!acl_is_trivial(&[2, 0, 0], 0o644));
+    }
+}
@@ -1,16 +1,28 @@
 extern crate chrono;
 extern crate users;
+extern crate libc;
+extern crate unicode_width;
 mod parsing;
 mod commands;
+use std::io;
+
 fn main() {
-    if !parsing::welcome::welcome().is_ok() {
+    if parsing::welcome::welcome().is_err() {
         return;
     }
 
+    // An inherited PWD may be stale or made up; pwd and cd rely on it
+    commands::pwd::check_inherited();
+
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+
     loop {
         match parsing::input::reading_input() {
-            Some(result) if !result.trim().is_empty() => {
-                println!("{}", result);
+            Some(tokens) if !tokens.is_empty() => {
+                let mut out = stdout.lock();
+                let mut err = stderr.lock();
+                parsing::input::execute(&tokens, &mut out, &mut err);
             }
             Some(_) => continue, // empty input → keep looping
             None => {
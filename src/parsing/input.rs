@@ -1,7 +1,8 @@
 use crate::{commands::handle_commands::handle_command, parsing::valide::validate_input};
-use std::io;
+use std::io::{self, Write};
 
-pub fn reading_input() -> Option<String> {
+/// Read one logical command line and split it into tokens
+pub fn reading_input() -> Option<Vec<String>> {
     let mut input = String::new();
     eprint!("$ ");
 
@@ -38,33 +39,48 @@ pub fn reading_input() -> Option<String> {
         trimmed.push_str(additional_input.trim_end());
     }
 
-    let tokens = tokenize(&trimmed);
-    if tokens.is_empty() {
-        return Some("".to_string());
-    }
+    Some(tokenize(&trimmed))
+}
 
+/// Run a tokenized command line, streaming its output to `out` and `err`.
+/// Returns the command's exit status.
+pub fn execute(tokens: &[String], out: &mut dyn Write, err: &mut dyn Write) -> i32 {
     let cmd = &tokens[0];
     let args = &tokens[1..];
 
-    let output = match validate_input(cmd) {
-        Some(_) => handle_command(cmd, args),
-        None => format!("Command '{}' not found", cmd),
+    let status = match validate_input(cmd) {
+        Some(_) => match handle_command(cmd, args, out, err) {
+            Ok(status) => status,
+            Err(e) => {
+                let _ = writeln!(err, "{}: write error: {}", cmd, e);
+                1
+            }
+        },
+        None => {
+            let _ = writeln!(err, "Command '{}' not found", cmd);
+            127
+        }
     };
 
-    Some(output)
+    let _ = out.flush();
+    let _ = err.flush();
+    status
 }
 
 /// Tokenize shell-like: single quotes preserve literally, double quotes allow escapes, spaces split outside quotes
 pub fn tokenize(input: &str) -> Vec<String> {
     let mut tokens = Vec::new();
     let mut current = String::new();
-    let mut chars = input.chars().peekable();
     let mut inside_single = false;
     let mut inside_double = false;
     let mut escaped = false;
 
-    while let Some(c) = chars.next() {
+    for c in input.chars() {
         if escaped {
+            // Inside double quotes a backslash only escapes what is special there
+            if inside_double && !matches!(c, '"' | '\\' | '$' | '`') {
+                current.push('\\');
+            }
             current.push(c);
             escaped = false;
             continue;
@@ -112,3 +128,20 @@ fn quotes_balanced(s: &str) -> bool {
 
     !inside_single && !inside_double
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backslashes_in_double_quotes() {
+        assert_eq!(tokenize(r#"echo "a\nb""#), ["echo", r"a\nb"]);
+        assert_eq!(tokenize(r#""say \"hi\" \\ \$HOME""#), [r#"say "hi" \ $HOME"#]);
+    }
+
+    #[test]
+    fn backslashes_elsewhere() {
+        assert_eq!(tokenize(r"a\ b \n"), ["a b", "n"]);
+        assert_eq!(tokenize(r"'a\nb'"), [r"a\nb"]);
+    }
+}
\ No newline at end of file
@@ -1,17 +1,10 @@
 pub fn validate_input(input: &str) -> Option<String> {
     let command = command_name(input);
-    let valid = match command {
-        "echo" | r#""echo""# | "cd" | "pwd"| "clear" |  "cat" | "cp" | "mv" | "mkdir" | "exit" => true,
-        "ls" => {
-            let mut  args = input.split_whitespace().skip(1);
-            args.all(|arg| arg == "-l" || arg == "-a" || arg == "-F")
-        }
-        "rm" => {
-            let mut args = input.split_whitespace().skip(1);
-            args.all(|arg| arg == "-r")
-        }
-        _ => false,
-    };
+    let valid = matches!(
+        command,
+        "echo" | "cd" | "pwd" | "clear" | "cat" | "cp" | "mv" | "mkdir" | "exit" | "ls" | "rm" | "trash"
+            | "journal" | "undo" | "umask" | "printf"
+    );
 
     if valid {
         Some(command.to_string())